@@ -1,6 +1,6 @@
 use sp_core::{Pair, Public, sr25519};
 use node_template_runtime::{
-	AccountId, AuraConfig, BalancesConfig, GenesisConfig, GrandpaConfig,
+	AccessConfig, AccountId, AuraConfig, BalancesConfig, GenesisConfig, GrandpaConfig,
 	SudoConfig, SystemConfig, WASM_BINARY, Signature
 };
 use sp_consensus_aura::sr25519::AuthorityId as AuraId;
@@ -151,8 +151,13 @@ fn testnet_genesis(
 		}),
 		pallet_sudo: Some(SudoConfig {
 			// Assign network admin rights.
-			key: root_key,
+			key: root_key.clone(),
+		}),
+		pallet_timekeeper: Some(Default::default()),
+		pallet_access: Some(AccessConfig {
+			// The network admin manages the whitelist, endowed accounts can transact from the start.
+			whitelist_admins: vec![root_key],
+			whitelist: endowed_accounts,
 		}),
-		pallet_timekeeper: Some(Default::default())
 	}
 }
@@ -0,0 +1,94 @@
+//! Access pallet benchmarking.
+
+#![cfg(feature = "runtime-benchmarks")]
+
+use super::*;
+use sp_std::prelude::*;
+use frame_system::RawOrigin;
+use frame_benchmarking::{benchmarks, account};
+
+use crate::Module as Access;
+
+const SEED: u32 = 0;
+const MAX_MEMBERS: u32 = 1_000;
+
+/// Create a role administered by `admin` with `m` members already in it
+fn setup_role<T: Trait>(m: u32) -> (T::Hash, T::AccountId) {
+	let admin_role = T::Hashing::hash(b"admin");
+	let role = T::Hashing::hash(b"role");
+	let admin: T::AccountId = account("admin", 0, SEED);
+	Access::<T>::add_account_to_role(admin_role, admin.clone()).expect("admin is not yet a member");
+	Access::<T>::set_admin_for_role(role, admin_role).expect("role has no admin yet");
+	for i in 0 .. m {
+		Access::<T>::add_account_to_role(role, account("member", i, SEED)).expect("member is new");
+	}
+	(role, admin)
+}
+
+benchmarks! {
+	_ { }
+
+	grant_role {
+		let m in 0 .. MAX_MEMBERS;
+		let (role, admin) = setup_role::<T>(m);
+		let account: T::AccountId = account("account", 0, SEED);
+	}: _(RawOrigin::Signed(admin), role, account.clone(), Some(10.into()))
+	verify {
+		assert!(Access::<T>::has_role(role, account));
+		assert_eq!(Access::<T>::member_count(role), m + 1);
+	}
+
+	set_role_expiry {
+		let m in 1 .. MAX_MEMBERS;
+		let (role, admin) = setup_role::<T>(m);
+		let account: T::AccountId = account("member", m - 1, SEED);
+	}: _(RawOrigin::Signed(admin), role, account.clone(), Some(10.into()))
+	verify {
+		assert_eq!(Access::<T>::expiries(role, account), Some(10.into()));
+	}
+
+	revoke_role {
+		let m in 1 .. MAX_MEMBERS;
+		let (role, admin) = setup_role::<T>(m);
+		let account: T::AccountId = account("member", m - 1, SEED);
+	}: _(RawOrigin::Signed(admin), role, account.clone())
+	verify {
+		assert!(!Access::<T>::has_role(role, account));
+		assert_eq!(Access::<T>::member_count(role), m - 1);
+	}
+
+	renounce_role {
+		let m in 1 .. MAX_MEMBERS;
+		let (role, _) = setup_role::<T>(m);
+		let account: T::AccountId = account("member", m - 1, SEED);
+	}: _(RawOrigin::Signed(account.clone()), role, account.clone())
+	verify {
+		assert!(!Access::<T>::has_role(role, account));
+	}
+
+	has_role {
+		let m in 1 .. MAX_MEMBERS;
+		let (role, _) = setup_role::<T>(m);
+		let account: T::AccountId = account("member", m - 1, SEED);
+	}: {
+		assert!(Access::<T>::has_role(role, account.clone()));
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::mock::{new_test_ext, Test};
+	use frame_support::assert_ok;
+
+	#[test]
+	fn test_benchmarks() {
+		new_test_ext().execute_with(|| {
+			assert_ok!(test_benchmark_grant_role::<Test>());
+			assert_ok!(test_benchmark_set_role_expiry::<Test>());
+			assert_ok!(test_benchmark_revoke_role::<Test>());
+			assert_ok!(test_benchmark_renounce_role::<Test>());
+			assert_ok!(test_benchmark_has_role::<Test>());
+		});
+	}
+}
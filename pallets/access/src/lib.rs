@@ -1,7 +1,17 @@
 #![cfg_attr(not(feature = "std"), no_std)]
-use frame_support::{decl_error, decl_event, decl_module, dispatch, decl_storage, ensure, traits::{Get}};
-use frame_system::ensure_signed;
+use codec::{Encode, Decode};
+use frame_support::{decl_error, decl_event, decl_module, dispatch, decl_storage, ensure, traits::{EnsureOrigin, Filter, Get}};
+use frame_support::storage::migration::StorageKeyIterator;
+use frame_support::weights::Weight;
+use frame_support::Blake2_128Concat;
+use frame_system::{ensure_root, ensure_signed, RawOrigin};
+use sp_runtime::RuntimeDebug;
+use sp_runtime::traits::{DispatchInfoOf, Hash, SignedExtension};
+use sp_runtime::transaction_validity::{
+	InvalidTransaction, TransactionValidity, TransactionValidityError, ValidTransaction,
+};
 use sp_std::prelude::*;
+use sp_std::collections::btree_set::BTreeSet;
 
 #[cfg(test)]
 mod mock;
@@ -9,16 +19,107 @@ mod mock;
 #[cfg(test)]
 mod tests;
 
+#[cfg(feature = "runtime-benchmarks")]
+mod benchmarking;
+
+/// Storage layouts of the pallet, to know which migrations have run
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq, RuntimeDebug)]
+pub enum Releases {
+	/// Members of a role stored in a single list
+	V1,
+	/// Members of a role stored in a double map with a counter
+	V2,
+}
+
+impl Default for Releases {
+	fn default() -> Self {
+		Releases::V1
+	}
+}
+
+/// Ensure the origin is signed by an account holding the role given by `Role`, resolving to the account.
+/// This allows the configuration of other pallets to gate calls on roles, for example
+/// `type ApproveOrigin = EnsureRole<Runtime, TreasurerRole>;`
+pub struct EnsureRole<T, Role>(sp_std::marker::PhantomData<(T, Role)>);
+
+impl<T: Trait, Role: Get<T::Hash>> EnsureOrigin<T::Origin> for EnsureRole<T, Role> {
+	type Success = T::AccountId;
+
+	fn try_origin(o: T::Origin) -> Result<Self::Success, T::Origin> {
+		o.into().and_then(|o| match o {
+			RawOrigin::Signed(who) if <Module<T>>::has_role(Role::get(), who.clone()) => Ok(who),
+			r => Err(T::Origin::from(r)),
+		})
+	}
+
+	#[cfg(feature = "runtime-benchmarks")]
+	fn successful_origin() -> T::Origin {
+		let who: T::AccountId = frame_benchmarking::account("role", 0, 0);
+		let _ = <Module<T>>::add_account_to_role(Role::get(), who.clone());
+		T::Origin::from(RawOrigin::Signed(who))
+	}
+}
+
+/// Custom validity error of transactions from accounts without the whitelist role
+pub const ROLE_REQUIRED: u8 = 1;
+
 pub trait Trait: frame_system::Trait {
 	type Event: From<Event<Self>> + Into<<Self as frame_system::Trait>::Event>;
+	/// The role accounts need to submit transactions, when `CheckRole` is used
+	type WhitelistRole: Get<Self::Hash>;
+	/// Calls accounts can submit without the whitelist role
+	type AlwaysAllowed: Filter<<Self as frame_system::Trait>::Call>;
+	/// Most ancestors a role can have, bounding the traversal of the hierarchy
+	type MaxRoleDepth: Get<u32>;
+	/// Most children a role can have, bounding the roles read when the hierarchy changes
+	type MaxChildRoles: Get<u32>;
 }
 
 decl_storage! {
 	trait Store for Module<T: Trait> as Access {
 		pub AdminRoles get(fn admin_roles):
 			map hasher(blake2_128_concat) T::Hash => T::Hash;
-		pub Roles get(fn roles): 
-			map hasher(blake2_128_concat) T::Hash => Vec<T::AccountId>;
+		/// Map whether an account is a member of a role
+		pub Members get(fn members):
+			double_map hasher(blake2_128_concat) T::Hash, hasher(blake2_128_concat) T::AccountId => bool;
+		/// Number of members of a role
+		pub MemberCount get(fn member_count): map hasher(blake2_128_concat) T::Hash => u32;
+		/// Map whether an account was granted a role, to find the roles of an account
+		pub RolesOf get(fn roles_of):
+			double_map hasher(blake2_128_concat) T::AccountId, hasher(blake2_128_concat) T::Hash => bool;
+		/// Role whose members also hold this role
+		pub ParentRoles get(fn parent_role): map hasher(blake2_128_concat) T::Hash => Option<T::Hash>;
+		/// Roles held by the members of this role
+		pub ChildRoles get(fn child_roles): map hasher(blake2_128_concat) T::Hash => Vec<T::Hash>;
+		/// Levels of descendants below a role, kept so moving a role doesn't walk its descendants
+		pub RoleHeights get(fn role_height): map hasher(blake2_128_concat) T::Hash => u32;
+		/// Block from which a time-bounded grant of a role no longer holds
+		pub Expiries get(fn expiries):
+			double_map hasher(blake2_128_concat) T::Hash, hasher(blake2_128_concat) T::AccountId => Option<T::BlockNumber>;
+		/// Grants to prune at a block, entries are skipped if the expiry has since changed
+		pub ExpiringRoles get(fn expiring_roles):
+			map hasher(blake2_128_concat) T::BlockNumber => Vec<(T::Hash, T::AccountId)>;
+		/// The storage layout in use
+		StorageVersion get(fn storage_version): Releases;
+	}
+	add_extra_genesis {
+		/// Accounts administering the whitelist, which are whitelisted themselves
+		config(whitelist_admins): Vec<T::AccountId>;
+		/// Accounts whitelisted to submit transactions
+		config(whitelist): Vec<T::AccountId>;
+		build(|config| {
+			let role = T::WhitelistRole::get();
+			let admin_role = <Module<T>>::whitelist_admin_role();
+			<Module<T>>::set_up_whitelist();
+			for account in config.whitelist_admins.iter() {
+				let _ = <Module<T>>::add_account_to_role(admin_role, account.clone());
+				let _ = <Module<T>>::add_account_to_role(role, account.clone());
+			}
+			for account in config.whitelist.iter() {
+				let _ = <Module<T>>::add_account_to_role(role, account.clone());
+			}
+			StorageVersion::put(Releases::V2);
+		})
 	}
 }
 
@@ -27,6 +128,7 @@ decl_event!(
 	where 
 	AccountId = <T as frame_system::Trait>::AccountId,
 	Hash = <T as frame_system::Trait>::Hash,
+	BlockNumber = <T as frame_system::Trait>::BlockNumber,
 	{
 		/// An role has been granted to an account
 		/// [caller, role, account]
@@ -37,6 +139,18 @@ decl_event!(
 		/// An role has been renounced for an account
 		/// [caller, role]
 		RoleRenounced(AccountId, Hash),
+		/// The expiry of a role granted to an account has changed
+		/// [caller, role, account, expiry]
+		RoleExpiryChanged(AccountId, Hash, AccountId, Option<BlockNumber>),
+		/// A role granted to an account has expired
+		/// [role, account]
+		RoleExpired(Hash, AccountId),
+		/// The parent of a role has changed
+		/// [caller, role, parent]
+		ParentRoleSet(AccountId, Hash, Option<Hash>),
+		/// A role has been granted to an account by root
+		/// [role, account]
+		RoleForceGranted(Hash, AccountId),
 	}
 );
 
@@ -48,6 +162,10 @@ decl_error! {
 		AdminRequired,
 		RenounceSelf,
 		AdminRoleExists,
+		ExpiryInPast,
+		RoleCycle,
+		HierarchyTooDeep,
+		TooManyChildRoles,
 	}
 }
 
@@ -55,16 +173,43 @@ decl_module! {
 	pub struct Module<T: Trait> for enum Call where origin: T::Origin {
 		fn deposit_event() = default;
 
-		#[weight = 10_000 + T::DbWeight::get().writes(1)]
-		pub fn grant_role(origin, role: T::Hash, account: T::AccountId) -> dispatch::DispatchResult {
+		fn on_initialize(now: T::BlockNumber) -> Weight {
+			Self::prune_expired_roles(now)
+		}
+
+		fn on_runtime_upgrade() -> Weight {
+			if Self::storage_version() == Releases::V1 {
+				Self::migrate_to_v2()
+			} else {
+				0
+			}
+		}
+
+		#[weight = 10_000 + T::DbWeight::get().reads_writes(5, 5)]
+		pub fn grant_role(origin, role: T::Hash, account: T::AccountId, expiry: Option<T::BlockNumber>) -> dispatch::DispatchResult {
 			let who = ensure_signed(origin)?;
 			ensure!(Self::has_role(Self::get_admin_of_role(role), who.clone()), Error::<T>::AdminRequired);
+			Self::ensure_future(expiry)?;
+			Self::prune_expired_role(role, &account);
 			Self::add_account_to_role(role, account.clone())?;
+			Self::set_expiry(role, &account, expiry);
 			Self::deposit_event(RawEvent::RoleGranted(who, role, account));
 			Ok(())
 		}
+
+		/// Extend or shorten a role granted to an account, `None` makes the grant permanent
+		#[weight = 10_000 + T::DbWeight::get().reads_writes(4, 2)]
+		pub fn set_role_expiry(origin, role: T::Hash, account: T::AccountId, expiry: Option<T::BlockNumber>) -> dispatch::DispatchResult {
+			let who = ensure_signed(origin)?;
+			ensure!(Self::has_role(Self::get_admin_of_role(role), who.clone()), Error::<T>::AdminRequired);
+			ensure!(Self::holds_role(role, &account), Error::<T>::NotMember);
+			Self::ensure_future(expiry)?;
+			Self::set_expiry(role, &account, expiry);
+			Self::deposit_event(RawEvent::RoleExpiryChanged(who, role, account, expiry));
+			Ok(())
+		}
 	
-		#[weight = 10_000 + T::DbWeight::get().writes(1)]
+		#[weight = 10_000 + T::DbWeight::get().reads_writes(3, 2)]
 		pub fn revoke_role(origin, role: T::Hash, account: T::AccountId) -> dispatch::DispatchResult {
 			let who = ensure_signed(origin)?;
 			ensure!(Self::has_role(Self::get_admin_of_role(role), who.clone()), Error::<T>::AdminRequired);
@@ -73,7 +218,7 @@ decl_module! {
 			Ok(())
 		}
 	
-		#[weight = 10_000 + T::DbWeight::get().writes(1)]
+		#[weight = 10_000 + T::DbWeight::get().reads_writes(1, 2)]
 		pub fn renounce_role(origin, role: T::Hash, account: T::AccountId) -> dispatch::DispatchResult {
 			let who = ensure_signed(origin)?;
 			ensure!(account == who, Error::<T>::RenounceSelf);
@@ -81,17 +226,138 @@ decl_module! {
 			Self::deposit_event(RawEvent::RoleRenounced(account, role));
 			Ok(())
 		}
+
+		/// Grant a role to an account as root, for instance to whitelist accounts through sudo
+		#[weight = 10_000 + T::DbWeight::get().reads_writes(3, 4)]
+		pub fn force_grant_role(origin, role: T::Hash, account: T::AccountId) -> dispatch::DispatchResult {
+			ensure_root(origin)?;
+			Self::prune_expired_role(role, &account);
+			Self::add_account_to_role(role, account.clone())?;
+			Self::deposit_event(RawEvent::RoleForceGranted(role, account));
+			Ok(())
+		}
+
+		/// Make the members of `parent` hold `role` as well, `None` detaches the role from its parent.
+		/// The caller has to administer both roles
+		#[weight = 10_000 + T::DbWeight::get().reads_writes(
+			(T::MaxRoleDepth::get() as Weight + 1) * (2 * T::MaxChildRoles::get() as Weight + 5) + 4,
+			2 * T::MaxRoleDepth::get() as Weight + 5,
+		)]
+		pub fn set_parent_role(origin, role: T::Hash, parent: Option<T::Hash>) -> dispatch::DispatchResult {
+			let who = ensure_signed(origin)?;
+			ensure!(Self::has_role(Self::get_admin_of_role(role), who.clone()), Error::<T>::AdminRequired);
+			if let Some(parent) = parent {
+				ensure!(Self::has_role(Self::get_admin_of_role(parent), who.clone()), Error::<T>::AdminRequired);
+			}
+			Self::set_parent_for_role(role, parent)?;
+			Self::deposit_event(RawEvent::ParentRoleSet(who, role, parent));
+			Ok(())
+		}
 	}
 }
 
 impl<T: Trait> Module<T> {
 
+	/// The role administering the whitelist role
+	pub fn whitelist_admin_role() -> T::Hash {
+		T::Hashing::hash_of(&(T::WhitelistRole::get(), "administrator"))
+	}
+
+	/// Let the whitelist administrators administer the whitelist and their own role
+	fn set_up_whitelist() {
+		let admin_role = Self::whitelist_admin_role();
+		let _ = Self::set_admin_for_role(T::WhitelistRole::get(), admin_role);
+		let _ = Self::set_admin_for_role(admin_role, admin_role);
+	}
+
+	/// Make `admin` a whitelisted whitelist administrator if the whitelist has no administrator,
+	/// as on a chain upgraded from before the whitelist, so it can whitelist the other accounts
+	pub fn seed_whitelist(admin: T::AccountId) -> Weight {
+		let admin_role = Self::whitelist_admin_role();
+		if Self::get_role_member_count(admin_role) > 0 {
+			return T::DbWeight::get().reads(1);
+		}
+		Self::set_up_whitelist();
+		let _ = Self::add_account_to_role(admin_role, admin.clone());
+		let _ = Self::add_account_to_role(T::WhitelistRole::get(), admin);
+		T::DbWeight::get().reads_writes(5, 8)
+	}
+
+	/// Whether the account holds the role, directly or through one of its ancestors
 	pub fn has_role(role: T::Hash, account: T::AccountId) -> bool {
-		Self::roles(role).contains(&account)
+		let mut current = Some(role);
+		let mut depth = 0;
+		while let Some(role) = current {
+			if Self::holds_role(role, &account) {
+				return true;
+			}
+			if depth >= T::MaxRoleDepth::get() {
+				break;
+			}
+			current = Self::parent_role(role);
+			depth += 1;
+		}
+		false
+	}
+
+	/// The roles an account holds, those granted to it and all of their descendants
+	pub fn effective_roles(account: T::AccountId) -> Vec<T::Hash> {
+		let mut roles = BTreeSet::new();
+		let mut level: Vec<T::Hash> = <RolesOf<T>>::iter_prefix(&account)
+			.map(|(role, _)| role)
+			.filter(|role| Self::holds_role(*role, &account))
+			.collect();
+		let mut depth = 0;
+		while !level.is_empty() && depth <= T::MaxRoleDepth::get() {
+			level = level.into_iter()
+				.filter(|role| roles.insert(*role))
+				.flat_map(|role| Self::child_roles(role))
+				.collect();
+			depth += 1;
+		}
+		roles.into_iter().collect()
+	}
+
+	/// Make the members of `parent` hold `role` as well, refusing cycles, hierarchies deeper than
+	/// `MaxRoleDepth` and parents with `MaxChildRoles` children already
+	pub fn set_parent_for_role(role: T::Hash, parent: Option<T::Hash>) -> dispatch::DispatchResult {
+		let max_depth = T::MaxRoleDepth::get();
+		if let Some(parent) = parent {
+			// Count the ancestors the role would have
+			let mut ancestors = 0;
+			let mut current = Some(parent);
+			while let Some(ancestor) = current {
+				ensure!(ancestor != role, Error::<T>::RoleCycle);
+				ancestors += 1;
+				ensure!(ancestors <= max_depth, Error::<T>::HierarchyTooDeep);
+				current = Self::parent_role(ancestor);
+			}
+			// The descendants of the role move down as well
+			ensure!(Self::role_height(role) <= max_depth - ancestors, Error::<T>::HierarchyTooDeep);
+			ensure!(Self::parent_role(role) == Some(parent) || (Self::child_roles(parent).len() as u32) < T::MaxChildRoles::get(),
+				Error::<T>::TooManyChildRoles);
+		}
+		if let Some(old) = Self::parent_role(role) {
+			<ChildRoles<T>>::mutate_exists(old, |children| {
+				*children = children.take()
+					.map(|c| c.into_iter().filter(|child| *child != role).collect::<Vec<_>>())
+					.filter(|c| !c.is_empty());
+			});
+			Self::update_heights(old);
+		}
+		match parent {
+			Some(parent) => {
+				<ChildRoles<T>>::append(parent, role);
+				<ParentRoles<T>>::insert(role, parent);
+				Self::update_heights(parent);
+			},
+			None => <ParentRoles<T>>::remove(role),
+		}
+		Ok(())
 	}
 	
 	pub fn get_role_member_count(role: T::Hash) -> usize {
-        Self::roles(role).len()
+        Self::member_count(role) as usize
     }
 
 	pub fn get_admin_of_role(role: T::Hash) -> T::Hash {
@@ -110,26 +376,168 @@ impl<T: Trait> Module<T> {
 	}
 	
 	fn add_member(role: T::Hash, account: T::AccountId) -> dispatch::DispatchResult {
-		let mut roles = Self::roles(role);
-		match roles.binary_search(&account) {
-			Ok(_) => Err(Error::<T>::AlreadyMember.into()),
-			Err(index) => {
-				roles.insert(index, account.clone());
-				<Roles<T>>::insert(role, roles);
-				Ok(())
+		ensure!(!Self::members(role, &account), Error::<T>::AlreadyMember);
+		<Members<T>>::insert(role, &account, true);
+		<RolesOf<T>>::insert(account, role, true);
+		<MemberCount<T>>::mutate(role, |count| *count = count.saturating_add(1));
+		Ok(())
+	}
+
+	fn remove_member(role: T::Hash, account: T::AccountId) -> dispatch::DispatchResult {
+		ensure!(Self::members(role, &account), Error::<T>::NotMember);
+		<Members<T>>::remove(role, &account);
+		<RolesOf<T>>::remove(&account, role);
+		<Expiries<T>>::remove(role, account);
+		<MemberCount<T>>::mutate_exists(role, |count| {
+			*count = count.map(|c| c.saturating_sub(1)).filter(|c| *c > 0);
+		});
+		Ok(())
+	}
+
+	/// Whether the account was granted the role and the grant hasn't expired
+	fn holds_role(role: T::Hash, account: &T::AccountId) -> bool {
+		Self::members(role, account) && !Self::has_expired(role, account)
+	}
+
+	/// Work out the height of a role from its children again, and of its ancestors as long as it changes
+	fn update_heights(role: T::Hash) {
+		let mut current = Some(role);
+		let mut depth = 0;
+		while let Some(role) = current {
+			let height = Self::child_roles(role).into_iter()
+				.map(|child| Self::role_height(child).saturating_add(1))
+				.max()
+				.unwrap_or(0);
+			if height == Self::role_height(role) {
+				break;
 			}
+			if height == 0 {
+				<RoleHeights<T>>::remove(role);
+			} else {
+				<RoleHeights<T>>::insert(role, height);
+			}
+			if depth >= T::MaxRoleDepth::get() {
+				break;
+			}
+			current = Self::parent_role(role);
+			depth += 1;
 		}
 	}
 
-	fn remove_member(role: T::Hash, account: T::AccountId) -> dispatch::DispatchResult {
-		let mut roles = Self::roles(role);
-		match roles.binary_search(&account) {
-			Ok(index) => {
-				roles.remove(index);
-				<Roles<T>>::insert(role, roles);
-				Ok(())
-			},
-			Err(_) => Err(Error::<T>::NotMember.into()),
+	fn has_expired(role: T::Hash, account: &T::AccountId) -> bool {
+		Self::expiries(role, account).map_or(false, |expiry| expiry <= <frame_system::Module<T>>::block_number())
+	}
+
+	fn ensure_future(expiry: Option<T::BlockNumber>) -> dispatch::DispatchResult {
+		if let Some(expiry) = expiry {
+			ensure!(expiry > <frame_system::Module<T>>::block_number(), Error::<T>::ExpiryInPast);
+		}
+		Ok(())
+	}
+
+	fn set_expiry(role: T::Hash, account: &T::AccountId, expiry: Option<T::BlockNumber>) {
+		<Expiries<T>>::mutate(role, account, |e| *e = expiry);
+		if let Some(expiry) = expiry {
+			<ExpiringRoles<T>>::append(expiry, (role, account.clone()));
+		}
+	}
+
+	/// Remove a grant of a role once it has expired
+	fn prune_expired_role(role: T::Hash, account: &T::AccountId) -> bool {
+		if Self::members(role, account) && Self::has_expired(role, account) {
+			let _ = Self::remove_member(role, account.clone());
+			Self::deposit_event(RawEvent::RoleExpired(role, account.clone()));
+			true
+		} else {
+			false
+		}
+	}
+
+	/// Remove the grants expiring at this block, skipping those since extended or revoked
+	fn prune_expired_roles(now: T::BlockNumber) -> Weight {
+		let expiring = <ExpiringRoles<T>>::take(now);
+		let mut reads: Weight = 1;
+		let mut writes: Weight = 1;
+		for (role, account) in expiring.iter() {
+			reads += 2;
+			if Self::expiries(role, account) == Some(now) && Self::prune_expired_role(*role, account) {
+				writes += 3;
+			}
+		}
+		T::DbWeight::get().reads_writes(reads, writes)
+	}
+
+	/// Move the members of each role from the list they were stored in to the double map
+	fn migrate_to_v2() -> Weight {
+		let roles: Vec<(T::Hash, Vec<T::AccountId>)> =
+			StorageKeyIterator::<T::Hash, Vec<T::AccountId>, Blake2_128Concat>::new(b"Access", b"Roles")
+				.drain()
+				.collect();
+		let mut writes: Weight = 1;
+		for (role, accounts) in roles {
+			let mut count: u32 = 0;
+			for account in accounts {
+				if !Self::members(role, &account) {
+					<Members<T>>::insert(role, &account, true);
+					<RolesOf<T>>::insert(account, role, true);
+					count = count.saturating_add(1);
+					writes += 2;
+				}
+			}
+			<MemberCount<T>>::insert(role, count);
+			writes += 2;
+		}
+		StorageVersion::put(Releases::V2);
+		T::DbWeight::get().reads_writes(writes, writes)
+	}
+}
+
+/// Reject transactions from accounts without the whitelist role, unless the call is always allowed.
+/// As a `SignedExtension` this applies when the transaction pool validates a transaction as well
+/// as when it is dispatched.
+#[derive(Encode, Decode, Clone, Eq, PartialEq)]
+pub struct CheckRole<T: Trait + Send + Sync>(sp_std::marker::PhantomData<T>);
+
+impl<T: Trait + Send + Sync> sp_std::fmt::Debug for CheckRole<T> {
+	#[cfg(feature = "std")]
+	fn fmt(&self, f: &mut sp_std::fmt::Formatter) -> sp_std::fmt::Result {
+		write!(f, "CheckRole")
+	}
+
+	#[cfg(not(feature = "std"))]
+	fn fmt(&self, _: &mut sp_std::fmt::Formatter) -> sp_std::fmt::Result {
+		Ok(())
+	}
+}
+
+impl<T: Trait + Send + Sync> CheckRole<T> {
+	pub fn new() -> Self {
+		Self(sp_std::marker::PhantomData)
+	}
+}
+
+impl<T: Trait + Send + Sync> SignedExtension for CheckRole<T> {
+	type AccountId = T::AccountId;
+	type Call = <T as frame_system::Trait>::Call;
+	type AdditionalSigned = ();
+	type Pre = ();
+	const IDENTIFIER: &'static str = "CheckRole";
+
+	fn additional_signed(&self) -> Result<(), TransactionValidityError> {
+		Ok(())
+	}
+
+	fn validate(
+		&self,
+		who: &Self::AccountId,
+		call: &Self::Call,
+		_info: &DispatchInfoOf<Self::Call>,
+		_len: usize,
+	) -> TransactionValidity {
+		if T::AlwaysAllowed::filter(call) || <Module<T>>::has_role(T::WhitelistRole::get(), who.clone()) {
+			Ok(ValidTransaction::default())
+		} else {
+			Err(InvalidTransaction::Custom(ROLE_REQUIRED).into())
 		}
 	}
 }
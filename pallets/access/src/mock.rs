@@ -3,7 +3,9 @@ use sp_core::H256;
 use frame_support::{
 	impl_outer_origin, 
 	impl_outer_event, 
+	impl_outer_dispatch,
 	parameter_types, 
+	traits::Filter,
 	weights::Weight,
 };
 use sp_runtime::{
@@ -24,6 +26,12 @@ impl_outer_event! {
 		access<T>,
 	}
 }
+
+impl_outer_dispatch! {
+	pub enum Call for Test where origin: Origin {
+		access::AccessModule,
+	}
+}
 // Configure a mock runtime to test the pallet.
 
 #[derive(Clone, Eq, PartialEq)]
@@ -38,7 +46,7 @@ parameter_types! {
 impl frame_system::Trait for Test {
 	type BaseCallFilter = ();
 	type Origin = Origin;
-	type Call = ();
+	type Call = Call;
 	type Index = u64;
 	type BlockNumber = u64;
 	type Hash = H256;
@@ -65,10 +73,25 @@ impl frame_system::Trait for Test {
 
 parameter_types! {
 	pub const MinimumPeriod: u64 = 5;
+	pub const WhitelistRole: H256 = H256::repeat_byte(9);
+	pub const MaxRoleDepth: u32 = 2;
+	pub const MaxChildRoles: u32 = 2;
+}
+
+/// Accounts can always renounce their roles
+pub struct AlwaysAllowed;
+impl Filter<Call> for AlwaysAllowed {
+	fn filter(call: &Call) -> bool {
+		matches!(call, Call::AccessModule(crate::Call::renounce_role(..)))
+	}
 }
 
 impl Trait for Test {
 	type Event = Event;
+	type WhitelistRole = WhitelistRole;
+	type AlwaysAllowed = AlwaysAllowed;
+	type MaxRoleDepth = MaxRoleDepth;
+	type MaxChildRoles = MaxChildRoles;
 }
 
 pub type AccessModule = Module<Test>;
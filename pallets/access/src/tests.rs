@@ -1,17 +1,46 @@
 use crate::{
+	CheckRole,
+	EnsureRole,
 	Error,
+	GenesisConfig,
+	ROLE_REQUIRED,
+	RawEvent,
+	Releases,
 	mock::*
 };
-use frame_support::{assert_ok, assert_noop};
+use codec::Encode;
+use frame_support::{assert_ok, assert_noop, parameter_types, traits::{EnsureOrigin, OnInitialize, OnRuntimeUpgrade}, StorageHasher, Blake2_128Concat};
+use frame_support::storage::migration::put_storage_value;
+use frame_support::weights::DispatchInfo;
 use sp_core::H256;
+use sp_runtime::BuildStorage;
+use sp_runtime::traits::SignedExtension;
+use sp_runtime::transaction_validity::InvalidTransaction;
 
 const NONE_ROLE : H256 = H256::repeat_byte(0);
 const ADMIN_ROLE : H256 = H256::repeat_byte(1);
 const WRITER_ROLE : H256 = H256::repeat_byte(2);
+const READER_ROLE : H256 = H256::repeat_byte(3);
+const GUEST_ROLE : H256 = H256::repeat_byte(4);
 const BOB : u64 = 101;
 const ALICE : u64 = 100;
 const CHARLIE : u64 = 102;
 
+parameter_types! {
+	pub const WriterRole: H256 = WRITER_ROLE;
+}
+
+fn run_to_block(n: u64) {
+	while System::block_number() < n {
+		System::set_block_number(System::block_number() + 1);
+		AccessModule::on_initialize(System::block_number());
+	}
+}
+
+fn last_event() -> Event {
+	System::events().pop().expect("an event").event
+}
+
 #[test]
 fn it_creates_and_grants_roles() {
 	new_test_ext().execute_with(|| {
@@ -21,9 +50,9 @@ fn it_creates_and_grants_roles() {
 		// Add Alice to Admin group
 		assert_ok!(AccessModule::add_account_to_role(ADMIN_ROLE, ALICE));
 		// Check storage
-		assert_eq!(AccessModule::roles(WRITER_ROLE).len(), 0);
-		assert_eq!(AccessModule::roles(ADMIN_ROLE).len(), 1);
-		assert_eq!(AccessModule::roles(ADMIN_ROLE)[0], ALICE);
+		assert_eq!(AccessModule::member_count(WRITER_ROLE), 0);
+		assert_eq!(AccessModule::member_count(ADMIN_ROLE), 1);
+		assert!(AccessModule::has_role(ADMIN_ROLE, ALICE));
 		// Set ADMIN, or ALICE, as administrator for WRITER ROLE
 		assert_ok!(AccessModule::set_admin_for_role(WRITER_ROLE, ADMIN_ROLE));
 		// Try to set it again will fail
@@ -31,13 +60,13 @@ fn it_creates_and_grants_roles() {
 		// Check we have this set in storage, the admin role for writer role should be 
 		assert_eq!(AccessModule::get_admin_of_role(WRITER_ROLE), ADMIN_ROLE);
 		// Grant BOB WRITER role by ALICE
-		assert_ok!(AccessModule::grant_role(Origin::signed(ALICE), WRITER_ROLE, BOB));
+		assert_ok!(AccessModule::grant_role(Origin::signed(ALICE), WRITER_ROLE, BOB, None));
 		// BOB should have WRITER role
 		assert!(AccessModule::has_role(WRITER_ROLE, BOB));
 		// ALICE should not have WRITER role
 		assert!(!AccessModule::has_role(WRITER_ROLE, ALICE));
 		// Grant CHARLIE WRITE ROLE by BOB, BOB should not be able to
-		assert_noop!(AccessModule::grant_role(Origin::signed(BOB), WRITER_ROLE, CHARLIE), Error::<Test>::AdminRequired);
+		assert_noop!(AccessModule::grant_role(Origin::signed(BOB), WRITER_ROLE, CHARLIE, None), Error::<Test>::AdminRequired);
 	});
 }
 
@@ -48,13 +77,13 @@ fn it_revokes_roles() {
 		assert_ok!(AccessModule::add_account_to_role(ADMIN_ROLE, ALICE));
 		assert_ok!(AccessModule::set_admin_for_role(WRITER_ROLE, ADMIN_ROLE));
 		// Grant BOB WRITER role by ALICE
-		assert_ok!(AccessModule::grant_role(Origin::signed(ALICE), WRITER_ROLE, BOB));
+		assert_ok!(AccessModule::grant_role(Origin::signed(ALICE), WRITER_ROLE, BOB, None));
 		// Revoke BOB WRITER role
 		assert_ok!(AccessModule::revoke_role(Origin::signed(ALICE), WRITER_ROLE, BOB));
 		// Check storage
 		assert!(!AccessModule::has_role(WRITER_ROLE, BOB));
 		// Grant BOB WRITER role by ALICE
-		assert_ok!(AccessModule::grant_role(Origin::signed(ALICE), WRITER_ROLE, BOB));
+		assert_ok!(AccessModule::grant_role(Origin::signed(ALICE), WRITER_ROLE, BOB, None));
 	});
 }
 
@@ -65,7 +94,7 @@ fn it_renounces_roles() {
 		assert_ok!(AccessModule::add_account_to_role(ADMIN_ROLE, ALICE));
 		assert_ok!(AccessModule::set_admin_for_role(WRITER_ROLE, ADMIN_ROLE));
 		// Grant BOB WRITER role by ALICE
-		assert_ok!(AccessModule::grant_role(Origin::signed(ALICE), WRITER_ROLE, BOB));
+		assert_ok!(AccessModule::grant_role(Origin::signed(ALICE), WRITER_ROLE, BOB, None));
 		// Renounce BOB WRITER role
 		// Should fail if the administrator role tries
 		assert_noop!(AccessModule::renounce_role(Origin::signed(ALICE), WRITER_ROLE, BOB), Error::<Test>::RenounceSelf);
@@ -74,4 +103,218 @@ fn it_renounces_roles() {
 		// Check storage
 		assert!(!AccessModule::has_role(WRITER_ROLE, BOB));
 	});
-}
\ No newline at end of file
+}
+
+#[test]
+fn it_counts_members_of_roles() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(AccessModule::add_account_to_role(ADMIN_ROLE, ALICE));
+		assert_ok!(AccessModule::set_admin_for_role(WRITER_ROLE, ADMIN_ROLE));
+		assert_ok!(AccessModule::grant_role(Origin::signed(ALICE), WRITER_ROLE, BOB, None));
+		assert_ok!(AccessModule::grant_role(Origin::signed(ALICE), WRITER_ROLE, CHARLIE, None));
+		// Granting again fails and doesn't count the member twice
+		assert_noop!(AccessModule::grant_role(Origin::signed(ALICE), WRITER_ROLE, BOB, None), Error::<Test>::AlreadyMember);
+		assert_eq!(AccessModule::get_role_member_count(WRITER_ROLE), 2);
+		assert_ok!(AccessModule::revoke_role(Origin::signed(ALICE), WRITER_ROLE, BOB));
+		assert_noop!(AccessModule::revoke_role(Origin::signed(ALICE), WRITER_ROLE, BOB), Error::<Test>::NotMember);
+		assert_eq!(AccessModule::get_role_member_count(WRITER_ROLE), 1);
+		assert_ok!(AccessModule::renounce_role(Origin::signed(CHARLIE), WRITER_ROLE, CHARLIE));
+		assert_eq!(AccessModule::get_role_member_count(WRITER_ROLE), 0);
+	});
+}
+
+#[test]
+fn it_migrates_members_stored_in_lists() {
+	new_test_ext().execute_with(|| {
+		// Store the members as the first release did
+		let key = |role: H256| Blake2_128Concat::hash(&role.encode());
+		put_storage_value(b"Access", b"Roles", &key(ADMIN_ROLE), vec![ALICE]);
+		put_storage_value(b"Access", b"Roles", &key(WRITER_ROLE), vec![ALICE, BOB, CHARLIE]);
+		assert_eq!(AccessModule::storage_version(), Releases::V1);
+		AccessModule::on_runtime_upgrade();
+		assert_eq!(AccessModule::storage_version(), Releases::V2);
+		assert!(AccessModule::has_role(ADMIN_ROLE, ALICE));
+		assert!(!AccessModule::has_role(ADMIN_ROLE, BOB));
+		assert!(AccessModule::has_role(WRITER_ROLE, BOB));
+		assert_eq!(AccessModule::member_count(ADMIN_ROLE), 1);
+		assert_eq!(AccessModule::member_count(WRITER_ROLE), 3);
+		// The lists are gone
+		assert!(frame_support::storage::migration::get_storage_value::<Vec<u64>>(b"Access", b"Roles", &key(WRITER_ROLE)).is_none());
+		// Upgrading again leaves the members alone
+		AccessModule::on_runtime_upgrade();
+		assert_eq!(AccessModule::member_count(WRITER_ROLE), 3);
+	});
+}
+
+#[test]
+fn it_expires_roles() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(AccessModule::add_account_to_role(ADMIN_ROLE, ALICE));
+		assert_ok!(AccessModule::set_admin_for_role(WRITER_ROLE, ADMIN_ROLE));
+		// The expiry has to be in the future
+		assert_noop!(AccessModule::grant_role(Origin::signed(ALICE), WRITER_ROLE, BOB, Some(1)), Error::<Test>::ExpiryInPast);
+		assert_ok!(AccessModule::grant_role(Origin::signed(ALICE), WRITER_ROLE, BOB, Some(5)));
+		assert_ok!(AccessModule::grant_role(Origin::signed(ALICE), WRITER_ROLE, CHARLIE, Some(5)));
+		// Only the admin can change the expiry
+		assert_noop!(AccessModule::set_role_expiry(Origin::signed(BOB), WRITER_ROLE, BOB, None), Error::<Test>::AdminRequired);
+		assert_noop!(AccessModule::set_role_expiry(Origin::signed(ALICE), WRITER_ROLE, ALICE, None), Error::<Test>::NotMember);
+		// Shorten BOB's grant and extend CHARLIE's
+		assert_ok!(AccessModule::set_role_expiry(Origin::signed(ALICE), WRITER_ROLE, BOB, Some(3)));
+		assert_ok!(AccessModule::set_role_expiry(Origin::signed(ALICE), WRITER_ROLE, CHARLIE, Some(8)));
+		assert_eq!(last_event(), Event::access(RawEvent::RoleExpiryChanged(ALICE, WRITER_ROLE, CHARLIE, Some(8))));
+		run_to_block(2);
+		assert!(AccessModule::has_role(WRITER_ROLE, BOB));
+		// Expired grants don't count even before they are pruned
+		System::set_block_number(3);
+		assert!(!AccessModule::has_role(WRITER_ROLE, BOB));
+		assert_eq!(AccessModule::member_count(WRITER_ROLE), 2);
+		AccessModule::on_initialize(3);
+		assert_eq!(last_event(), Event::access(RawEvent::RoleExpired(WRITER_ROLE, BOB)));
+		assert_eq!(AccessModule::member_count(WRITER_ROLE), 1);
+		assert_eq!(AccessModule::expiries(WRITER_ROLE, BOB), None);
+		// CHARLIE's old expiry is skipped
+		run_to_block(5);
+		assert!(AccessModule::has_role(WRITER_ROLE, CHARLIE));
+		run_to_block(8);
+		assert!(!AccessModule::has_role(WRITER_ROLE, CHARLIE));
+		assert_eq!(AccessModule::member_count(WRITER_ROLE), 0);
+		// A permanent grant never expires
+		assert_ok!(AccessModule::grant_role(Origin::signed(ALICE), WRITER_ROLE, BOB, Some(10)));
+		assert_ok!(AccessModule::set_role_expiry(Origin::signed(ALICE), WRITER_ROLE, BOB, None));
+		run_to_block(12);
+		assert!(AccessModule::has_role(WRITER_ROLE, BOB));
+	});
+}
+
+#[test]
+fn it_ensures_origins_hold_a_role() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(AccessModule::add_account_to_role(ADMIN_ROLE, ALICE));
+		assert_ok!(AccessModule::set_admin_for_role(WRITER_ROLE, ADMIN_ROLE));
+		assert_ok!(AccessModule::grant_role(Origin::signed(ALICE), WRITER_ROLE, BOB, Some(3)));
+		assert_eq!(EnsureRole::<Test, WriterRole>::try_origin(Origin::signed(BOB)).ok(), Some(BOB));
+		assert!(EnsureRole::<Test, WriterRole>::ensure_origin(Origin::signed(ALICE)).is_err());
+		assert!(EnsureRole::<Test, WriterRole>::ensure_origin(Origin::root()).is_err());
+		assert!(EnsureRole::<Test, WriterRole>::ensure_origin(Origin::none()).is_err());
+		// An expired grant no longer passes
+		System::set_block_number(3);
+		assert!(EnsureRole::<Test, WriterRole>::ensure_origin(Origin::signed(BOB)).is_err());
+	});
+}
+
+fn whitelisted_test_ext() -> sp_io::TestExternalities {
+	let mut t = frame_system::GenesisConfig::default().build_storage::<Test>().unwrap();
+	GenesisConfig::<Test> {
+		whitelist_admins: vec![ALICE],
+		whitelist: vec![BOB],
+	}.assimilate_storage(&mut t).unwrap();
+	let mut t: sp_io::TestExternalities = t.into();
+	t.execute_with(|| System::set_block_number(1));
+	t
+}
+
+#[test]
+fn it_only_accepts_transactions_from_whitelisted_accounts() {
+	whitelisted_test_ext().execute_with(|| {
+		let whitelist = WhitelistRole::get();
+		let grant = Call::AccessModule(crate::Call::grant_role(whitelist, CHARLIE, None));
+		let renounce = Call::AccessModule(crate::Call::renounce_role(ADMIN_ROLE, CHARLIE));
+		let info = DispatchInfo::default();
+		assert!(AccessModule::has_role(whitelist, ALICE));
+		assert!(AccessModule::has_role(whitelist, BOB));
+		assert_eq!(AccessModule::get_admin_of_role(whitelist), AccessModule::whitelist_admin_role());
+		assert!(CheckRole::<Test>::new().validate(&BOB, &grant, &info, 0).is_ok());
+		// Rejected when validated in the pool and when dispatched
+		assert_eq!(CheckRole::<Test>::new().validate(&CHARLIE, &grant, &info, 0), Err(InvalidTransaction::Custom(ROLE_REQUIRED).into()));
+		assert!(CheckRole::<Test>::new().pre_dispatch(&CHARLIE, &grant, &info, 0).is_err());
+		// Some calls are always allowed
+		assert!(CheckRole::<Test>::new().validate(&CHARLIE, &renounce, &info, 0).is_ok());
+		// Only the administrators of the whitelist can add to it
+		assert_noop!(AccessModule::grant_role(Origin::signed(BOB), whitelist, CHARLIE, None), Error::<Test>::AdminRequired);
+		assert_ok!(AccessModule::grant_role(Origin::signed(ALICE), whitelist, CHARLIE, Some(3)));
+		assert!(CheckRole::<Test>::new().validate(&CHARLIE, &grant, &info, 0).is_ok());
+		// Until the grant expires
+		System::set_block_number(3);
+		assert!(CheckRole::<Test>::new().validate(&CHARLIE, &grant, &info, 0).is_err());
+	});
+}
+
+#[test]
+fn it_seeds_the_whitelist_of_upgraded_chains() {
+	new_test_ext().execute_with(|| {
+		let whitelist = WhitelistRole::get();
+		// The first administrator is only seeded once
+		AccessModule::seed_whitelist(ALICE);
+		assert!(AccessModule::has_role(whitelist, ALICE));
+		assert!(AccessModule::has_role(AccessModule::whitelist_admin_role(), ALICE));
+		AccessModule::seed_whitelist(BOB);
+		assert!(!AccessModule::has_role(whitelist, BOB));
+		// And whitelists the other accounts
+		assert_ok!(AccessModule::grant_role(Origin::signed(ALICE), whitelist, BOB, None));
+		// Root can grant any role, for instance through sudo
+		assert_noop!(AccessModule::force_grant_role(Origin::signed(ALICE), WRITER_ROLE, CHARLIE), frame_support::error::BadOrigin);
+		assert_ok!(AccessModule::force_grant_role(Origin::root(), WRITER_ROLE, CHARLIE));
+		assert_eq!(last_event(), Event::access(RawEvent::RoleForceGranted(WRITER_ROLE, CHARLIE)));
+		assert!(AccessModule::has_role(WRITER_ROLE, CHARLIE));
+	});
+}
+
+#[test]
+fn it_inherits_roles_from_parents() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(AccessModule::add_account_to_role(ADMIN_ROLE, ALICE));
+		assert_ok!(AccessModule::set_admin_for_role(ADMIN_ROLE, ADMIN_ROLE));
+		assert_ok!(AccessModule::set_admin_for_role(WRITER_ROLE, ADMIN_ROLE));
+		assert_ok!(AccessModule::set_admin_for_role(READER_ROLE, ADMIN_ROLE));
+		assert_ok!(AccessModule::set_admin_for_role(GUEST_ROLE, ADMIN_ROLE));
+		// Only the admin of a role can set its parent
+		assert_noop!(AccessModule::set_parent_role(Origin::signed(BOB), WRITER_ROLE, Some(ADMIN_ROLE)), Error::<Test>::AdminRequired);
+		// ADMIN > WRITER > READER
+		assert_ok!(AccessModule::set_parent_role(Origin::signed(ALICE), WRITER_ROLE, Some(ADMIN_ROLE)));
+		assert_eq!(last_event(), Event::access(RawEvent::ParentRoleSet(ALICE, WRITER_ROLE, Some(ADMIN_ROLE))));
+		assert_ok!(AccessModule::set_parent_role(Origin::signed(ALICE), READER_ROLE, Some(WRITER_ROLE)));
+		assert_ok!(AccessModule::grant_role(Origin::signed(ALICE), WRITER_ROLE, BOB, None));
+		// And of the parent
+		let drafts = H256::repeat_byte(8);
+		assert_ok!(AccessModule::set_admin_for_role(drafts, WRITER_ROLE));
+		assert_noop!(AccessModule::set_parent_role(Origin::signed(BOB), drafts, Some(ADMIN_ROLE)), Error::<Test>::AdminRequired);
+		assert!(AccessModule::has_role(READER_ROLE, ALICE));
+		assert!(AccessModule::has_role(READER_ROLE, BOB));
+		assert!(!AccessModule::has_role(ADMIN_ROLE, BOB));
+		// Inherited roles aren't counted as members
+		assert_eq!(AccessModule::member_count(READER_ROLE), 0);
+		assert_eq!(AccessModule::effective_roles(ALICE), vec![ADMIN_ROLE, WRITER_ROLE, READER_ROLE]);
+		assert_eq!(AccessModule::effective_roles(BOB), vec![WRITER_ROLE, READER_ROLE]);
+		assert!(AccessModule::effective_roles(CHARLIE).is_empty());
+		// Cycles are refused
+		assert_noop!(AccessModule::set_parent_role(Origin::signed(ALICE), ADMIN_ROLE, Some(READER_ROLE)), Error::<Test>::RoleCycle);
+		assert_noop!(AccessModule::set_parent_role(Origin::signed(ALICE), ADMIN_ROLE, Some(ADMIN_ROLE)), Error::<Test>::RoleCycle);
+		// So are hierarchies deeper than the limit, from above or below
+		assert_noop!(AccessModule::set_parent_role(Origin::signed(ALICE), GUEST_ROLE, Some(READER_ROLE)), Error::<Test>::HierarchyTooDeep);
+		assert_ok!(AccessModule::set_parent_role(Origin::signed(ALICE), GUEST_ROLE, Some(WRITER_ROLE)));
+		let top = H256::repeat_byte(7);
+		assert_ok!(AccessModule::set_admin_for_role(top, ADMIN_ROLE));
+		assert_noop!(AccessModule::set_parent_role(Origin::signed(ALICE), ADMIN_ROLE, Some(top)), Error::<Test>::HierarchyTooDeep);
+		// And roles with too many children
+		let editor = H256::repeat_byte(6);
+		assert_ok!(AccessModule::set_admin_for_role(editor, ADMIN_ROLE));
+		assert_noop!(AccessModule::set_parent_role(Origin::signed(ALICE), editor, Some(WRITER_ROLE)), Error::<Test>::TooManyChildRoles);
+		assert_eq!(AccessModule::role_height(ADMIN_ROLE), 2);
+		// Detaching a role removes what it inherited
+		assert_ok!(AccessModule::set_parent_role(Origin::signed(ALICE), READER_ROLE, None));
+		assert!(!AccessModule::has_role(READER_ROLE, BOB));
+		assert_eq!(AccessModule::child_roles(WRITER_ROLE), vec![GUEST_ROLE]);
+		assert_eq!(AccessModule::effective_roles(BOB), vec![WRITER_ROLE, GUEST_ROLE]);
+		// Heights follow the hierarchy
+		assert_ok!(AccessModule::set_parent_role(Origin::signed(ALICE), GUEST_ROLE, None));
+		assert_eq!(AccessModule::role_height(WRITER_ROLE), 0);
+		assert_eq!(AccessModule::role_height(ADMIN_ROLE), 1);
+		assert_ok!(AccessModule::set_parent_role(Origin::signed(ALICE), GUEST_ROLE, Some(WRITER_ROLE)));
+		assert_eq!(AccessModule::role_height(ADMIN_ROLE), 2);
+		// Expired grants give no roles
+		assert_ok!(AccessModule::set_role_expiry(Origin::signed(ALICE), WRITER_ROLE, BOB, Some(3)));
+		System::set_block_number(3);
+		assert!(!AccessModule::has_role(GUEST_ROLE, BOB));
+		assert!(AccessModule::effective_roles(BOB).is_empty());
+	});
+}
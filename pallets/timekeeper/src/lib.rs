@@ -1,13 +1,22 @@
 #![cfg_attr(not(feature = "std"), no_std)]
 
-use dispatch::DispatchResult;
-use frame_support::sp_std::convert::TryInto;
-
-use frame_support::{decl_error, decl_event, decl_module, decl_storage, dispatch, ensure, traits::{Currency, ExistenceRequirement, Get}};
-use frame_support::weights::{DispatchClass, Pays};
-use frame_system::{ensure_signed, ensure_root};
-use sp_runtime::ModuleId;
-use sp_runtime::traits::{AccountIdConversion};
+use codec::{Encode, Decode};
+use dispatch::{DispatchError, DispatchResult};
+use frame_support::sp_std::prelude::*;
+use frame_support::sp_std::cell::Cell;
+
+use frame_support::{decl_error, decl_event, decl_module, decl_storage, dispatch, ensure, Parameter, traits::{Currency, EnsureOrigin, ExistenceRequirement, Get, Randomness}};
+use frame_support::{Blake2_128Concat, ReversibleStorageHasher};
+use frame_support::sp_io;
+use frame_support::storage::{IterableStorageMap, StoragePrefixedMap};
+use frame_support::storage::migration::{take_storage_value, StorageKeyIterator};
+use frame_support::weights::{DispatchClass, Pays, Weight};
+use frame_system::{ensure_signed, ensure_root, ensure_none};
+use sp_runtime::{FixedPointNumber, FixedU128, ModuleId, Perbill, PerThing, RuntimeDebug};
+use sp_runtime::traits::{AccountIdConversion, IdentifyAccount, One, SaturatedConversion, Saturating, Verify, Zero};
+use sp_runtime::transaction_validity::{
+	InvalidTransaction, TransactionPriority, TransactionSource, TransactionValidity, ValidTransaction,
+};
 use pallet_timestamp as timestamp;
 use pallet_access as access;
 use orml_utilities::with_transaction_result;
@@ -20,51 +29,647 @@ mod mock;
 mod tests;
 
 const PALLET_ID: ModuleId = ModuleId(*b"timekeep");
+const MILLISECS_PER_MINUTE: u128 = 60 * 1000;
+const MILLISECS_PER_HOUR: u128 = 60 * MILLISECS_PER_MINUTE;
+/// Daily rates are paid per working day of 8 hours
+const MILLISECS_PER_WORKING_DAY: u128 = 8 * MILLISECS_PER_HOUR;
+const MILLISECS_PER_DAY: u128 = 24 * MILLISECS_PER_HOUR;
+/// Multiplier, in percent, of time paid at the plain rate
+const BASE_MULTIPLIER: u32 = 100;
+/// Maximum number of breaks an account can take in a shift
+const MAX_BREAKS: usize = 16;
+/// Priority of punches from time-clock devices in the transaction pool
+const PUNCH_PRIORITY: TransactionPriority = TransactionPriority::max_value() / 2;
+/// Number of blocks a punch stays valid in the transaction pool, punches are timed when included
+const PUNCH_LONGEVITY: u64 = 5;
+/// Challenge codes have six digits so they can be typed in from the kiosk
+const CHALLENGE_CODE_MODULUS: u32 = 1_000_000;
+/// The organization accounts registered before there were organizations belong to
+const LEGACY_ORG: OrgId = 0;
 type AccountIdOf<T> = <T as frame_system::Trait>::AccountId;
-type BalanceOf<T> = <<T as Trait>::Currency as Currency<AccountIdOf<T>>>::Balance;
+pub type BalanceOf<T> = <<T as Trait>::Currency as Currency<AccountIdOf<T>>>::Balance;
+pub type ProjectId = u32;
+pub type OrgId = u32;
+pub type SiteId = u32;
+
+/// An employer with its own pot, roles and registered accounts
+#[derive(Encode, Decode, Clone, Default, PartialEq, Eq, RuntimeDebug)]
+pub struct Organization<Hash> {
+	/// The role of the administrators of the organization
+	pub admin_role: Hash,
+	/// The role of the accounts which register workers with the organization
+	pub registrar_role: Hash,
+	/// The role of the accounts which review the shifts of workers of the organization
+	pub approver_role: Hash,
+	/// The role of the accounts which enter and exit the workers assigned to them
+	pub supervisor_role: Hash,
+}
+
+/// The rate an account is paid at and the unit it is quoted in
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq, RuntimeDebug)]
+pub enum RateSpec<Balance> {
+	/// Paid per hour worked
+	Hourly(Balance),
+	/// Paid per minute worked
+	PerMinute(Balance),
+	/// Paid per working day of 8 hours worked
+	Daily(Balance),
+	/// Paid a fixed salary each payroll period, whether the account enters or not
+	Salaried(Balance),
+}
+
+impl<Balance: Copy> RateSpec<Balance> {
+	/// The amount paid per unit
+	pub fn amount(&self) -> Balance {
+		match self {
+			RateSpec::Hourly(amount)
+			| RateSpec::PerMinute(amount)
+			| RateSpec::Daily(amount)
+			| RateSpec::Salaried(amount) => *amount,
+		}
+	}
+
+	/// The milliseconds worked the amount is paid for, salaries aren't paid for time worked
+	pub fn unit_millisecs(&self) -> Option<u128> {
+		match self {
+			RateSpec::Hourly(_) => Some(MILLISECS_PER_HOUR),
+			RateSpec::PerMinute(_) => Some(MILLISECS_PER_MINUTE),
+			RateSpec::Daily(_) => Some(MILLISECS_PER_WORKING_DAY),
+			RateSpec::Salaried(_) => None,
+		}
+	}
+}
+
+/// Multipliers applied to the rate of accounts for overtime, night and weekend hours. Days start
+/// at midnight UTC and weeks on Monday, when several multipliers apply the highest is paid
+#[derive(Encode, Decode, Clone, Default, PartialEq, Eq, RuntimeDebug)]
+pub struct PayPolicy<Moment> {
+	/// Time worked in a day after which overtime is paid
+	pub daily_threshold: Option<Moment>,
+	/// Time worked in a week after which overtime is paid
+	pub weekly_threshold: Option<Moment>,
+	/// Multiplier for overtime, in percent so 150 pays time and a half
+	pub overtime_multiplier: u32,
+	/// Start and end of the night, in milliseconds since midnight. The night spans midnight when
+	/// it ends before it starts
+	pub night: Option<(Moment, Moment)>,
+	/// Multiplier for night hours, in percent
+	pub night_multiplier: u32,
+	/// Multiplier for Saturday and Sunday hours, in percent
+	pub weekend_multiplier: u32,
+}
+
+/// Time worked by an account in its latest day and week, to find when overtime starts
+#[derive(Encode, Decode, Clone, Default, PartialEq, Eq, RuntimeDebug)]
+pub struct WorkedTime<Moment> {
+	/// The day worked, counted in days since the epoch
+	pub day: u32,
+	/// Time worked in the day
+	pub daily: Moment,
+	/// The week worked, counted in weeks since the Monday before the epoch
+	pub week: u32,
+	/// Time worked in the week
+	pub weekly: Moment,
+}
+
+/// A project time is billed to, with optional budgets
+#[derive(Encode, Decode, Clone, Default, PartialEq, Eq, RuntimeDebug)]
+pub struct Project<Moment, Balance> {
+	/// The organization the project belongs to
+	pub org: OrgId,
+	/// Time which can be worked on the project
+	pub time_budget: Option<Moment>,
+	/// Credit which can be earned on the project
+	pub credit_budget: Option<Balance>,
+	/// Time worked on the project
+	pub time: Moment,
+	/// Credit earned on the project
+	pub credit: Balance,
+}
+
+/// A break taken by an account during a shift
+#[derive(Encode, Decode, Clone, Default, PartialEq, Eq, RuntimeDebug)]
+pub struct BreakRecord<Moment> {
+	pub start: Moment,
+	pub end: Moment,
+	/// Whether the break is paid, unpaid breaks aren't credited
+	pub paid: bool,
+}
+
+/// Part of a shift paid at the same multiplier of the rate
+#[derive(Encode, Decode, Clone, Default, PartialEq, Eq, RuntimeDebug)]
+pub struct ShiftSegment<Moment, Balance> {
+	pub start: Moment,
+	pub end: Moment,
+	/// Multiplier applied to the rate, in percent
+	pub multiplier: u32,
+	/// The credit earned for the segment
+	pub credit: Balance,
+}
+
+/// Whether the credit of a shift can be withdrawn
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq, RuntimeDebug)]
+pub enum ShiftStatus {
+	/// Waiting to be reviewed by an approver, the credit isn't withdrawable yet
+	Pending,
+	/// Reviewed by an approver or approved automatically, the credit has been added to the account
+	Approved,
+	/// Rejected by an approver with a reason code, the credit is never paid
+	Rejected(u8),
+}
+
+impl Default for ShiftStatus {
+	fn default() -> Self {
+		ShiftStatus::Pending
+	}
+}
+
+/// A shift worked by an account, from entering to exiting
+#[derive(Encode, Decode, Clone, Default, PartialEq, Eq, RuntimeDebug)]
+pub struct ShiftRecord<AccountId, Moment, Balance> {
+	/// The organization the shift was worked for
+	pub org: OrgId,
+	/// When the account entered
+	pub start: Moment,
+	/// When the account exited
+	pub end: Moment,
+	/// The time credited for the shift
+	pub duration: Moment,
+	/// The rate applied, if the account has one
+	pub rate: Option<RateSpec<Balance>>,
+	/// The credit earned for the shift
+	pub credit: Balance,
+	/// Whether the account was exited automatically after its maximum shift duration
+	pub auto_exited: bool,
+	/// The parts of the shift paid at different multipliers of the rate
+	pub segments: Vec<ShiftSegment<Moment, Balance>>,
+	/// The breaks taken during the shift, including a break deducted automatically
+	pub breaks: Vec<BreakRecord<Moment>>,
+	/// The project the shift was worked on
+	pub project: Option<ProjectId>,
+	/// Whether the shift has been reviewed
+	pub status: ShiftStatus,
+	/// The supervisor who entered the account, if it didn't enter itself
+	pub entered_by: Option<AccountId>,
+	/// The supervisor who exited the account, if it didn't exit itself
+	pub exited_by: Option<AccountId>,
+}
+
+/// Storage layouts of the pallet, to know which migrations have run
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq, RuntimeDebug)]
+pub enum Releases {
+	/// The first release, with rates stored as plain hourly balances and a single registrar role
+	V1,
+	/// Rates quoted with their unit and accounts registered with organizations
+	V2,
+}
+
+impl Default for Releases {
+	fn default() -> Self {
+		Releases::V1
+	}
+}
+
+/// Whether a correction request has been reviewed by an administrator
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq, RuntimeDebug)]
+pub enum CorrectionStatus {
+	Pending,
+	Approved,
+	Denied,
+}
+
+impl Default for CorrectionStatus {
+	fn default() -> Self {
+		CorrectionStatus::Pending
+	}
+}
+
+/// Enter and exit times proposed by an account for one of its shifts, kept with the original
+/// times once reviewed
+#[derive(Encode, Decode, Clone, Default, PartialEq, Eq, RuntimeDebug)]
+pub struct CorrectionRequest<Moment, Hash> {
+	/// The corrected enter time
+	pub start: Moment,
+	/// The corrected exit time
+	pub end: Moment,
+	/// Hash of the justification for the correction, which is kept off chain
+	pub justification: Hash,
+	/// The enter and exit times before the correction, there is no exit time for an open shift
+	pub original: (Moment, Option<Moment>),
+	pub status: CorrectionStatus,
+}
+
+/// A time-clock device workers punch in and out on
+#[derive(Encode, Decode, Clone, Default, PartialEq, Eq, RuntimeDebug)]
+pub struct Device {
+	/// The organization which registered the device
+	pub org: OrgId,
+	/// The site the device is installed at
+	pub site: SiteId,
+	/// Whether punches on the device are accepted
+	pub active: bool,
+}
+
+/// Whether a punch enters or exits the worker
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq, RuntimeDebug)]
+pub enum PunchKind {
+	Enter,
+	Exit,
+}
+
+/// A punch on a time-clock device, signed by both the worker and the device
+#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug)]
+pub struct Punch<AccountId> {
+	/// The worker punching
+	pub worker: AccountId,
+	/// The account of the device key
+	pub device: AccountId,
+	pub kind: PunchKind,
+	/// The number of punches the worker has made, so a punch can't be replayed
+	pub nonce: u32,
+}
+
+/// A punch recorded by a time-clock device while offline, signed by both the worker and the
+/// device and applied at the time it was recorded
+#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug)]
+pub struct OfflinePunch<AccountId, Moment> {
+	/// The worker punching
+	pub worker: AccountId,
+	/// The account of the device key
+	pub device: AccountId,
+	pub kind: PunchKind,
+	/// The number of punches the worker has made, so a punch can't be replayed
+	pub nonce: u32,
+	/// When the punch was recorded
+	pub time: Moment,
+}
+
+/// The challenge code shown on the kiosk of a site at a block, proving the account is on site
+#[derive(Encode, Decode, Clone, Default, PartialEq, Eq, RuntimeDebug)]
+pub struct Challenge<BlockNumber> {
+	pub site: SiteId,
+	/// The block the code was shown at
+	pub block: BlockNumber,
+	pub code: u32,
+}
+
+/// How the payroll pays creditors when the pot of an organization can't cover all the credit owed
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq, RuntimeDebug)]
+pub enum SettlementMode {
+	/// Pay creditors in full in queue order, those which can't be paid wait for the next run
+	Full,
+	/// Pay every creditor the same fraction of its credit, the rest is carried over
+	ProRata,
+}
+
+impl Default for SettlementMode {
+	fn default() -> Self {
+		SettlementMode::Full
+	}
+}
+
+/// A rate change for an account proposed by an administrator or the account holder, which is
+/// applied once the other party accepts it
+#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug)]
+pub struct RateProposal<AccountId, Balance, BlockNumber> {
+	/// The administrator or account holder who proposed the rate
+	pub proposer: AccountId,
+	/// The proposed rate
+	pub rate: Option<RateSpec<Balance>>,
+	/// The block after which the proposal can no longer be accepted
+	pub expires: BlockNumber,
+}
 
 pub trait Trait: timestamp::Trait + access::Trait {
 	type Event: From<Event<Self>> + Into<<Self as frame_system::Trait>::Event>;
 	type Currency: Currency<Self::AccountId>;
+	/// The maximum time an account can stay entered before it is exited automatically
+	type MaxShiftDuration: Get<Self::Moment>;
+	/// The number of blocks between payroll runs
+	type PayrollPeriod: Get<Self::BlockNumber>;
+	/// The maximum number of accounts paid in a single block
+	type PayrollBatchSize: Get<u32>;
+	/// The maximum number of entered accounts checked for their maximum shift duration in a single block
+	type AutoExitBatchSize: Get<u32>;
+	/// The number of blocks a rate proposal stays open for
+	type RateProposalExpiry: Get<Self::BlockNumber>;
+	/// Shifts longer than this without a break have a break deducted automatically, zero disables it
+	type AutoBreakAfter: Get<Self::Moment>;
+	/// The unpaid break deducted from the end of long shifts without a break
+	type AutoBreakDuration: Get<Self::Moment>;
+	/// The public key of workers and time-clock devices signing punches
+	type Public: IdentifyAccount<AccountId = Self::AccountId> + Parameter;
+	/// The signature of a punch by a worker or time-clock device
+	type Signature: Verify<Signer = Self::Public> + Parameter;
+	/// The source of randomness challenge codes are derived from
+	type Randomness: Randomness<Self::Hash>;
+	/// The oldest challenge code an organization can accept, in blocks, zero disables challenges
+	type MaxChallengeAge: Get<Self::BlockNumber>;
+	/// How long before it is submitted an offline punch can have been recorded
+	type MaxBackdating: Get<Self::Moment>;
+	/// The maximum number of offline punches submitted in one batch
+	type MaxOfflinePunches: Get<u32>;
 }
 
 decl_storage! {
 	trait Store for Module<T: Trait> as Timekeeper {
+		/// The role of the administrators who create organizations
 		pub AdminRole get(fn admin_role): T::Hash;
-		pub RegistrarRole get(fn registrar_role): T::Hash;
-		/// Store the rate for an account
-		pub Rates get(fn rates): map hasher(blake2_128_concat) T::AccountId => Option<BalanceOf<T>>;
-		/// Store a list of creditors for work done
-		pub Creditors get(fn creditors): map hasher(blake2_128_concat) T::AccountId => Option<BalanceOf<T>>;
-		/// Map whether account is in or out
-		pub Entered get(fn entered): map hasher(blake2_128_concat) T::AccountId => Option<T::Moment>;
+		/// Store the organizations, by organization id
+		pub Organizations get(fn organizations): map hasher(twox_64_concat) OrgId => Option<Organization<T::Hash>>;
+		/// Number of organizations created, which is also the id of the next organization
+		pub OrganizationCount get(fn organization_count): OrgId;
+		/// Store the rate for an account registered with an organization
+		pub Rates get(fn rates):
+			double_map hasher(twox_64_concat) OrgId, hasher(blake2_128_concat) T::AccountId => Option<RateSpec<BalanceOf<T>>>;
+		/// Store a list of creditors for work done, by organization
+		pub Creditors get(fn creditors):
+			double_map hasher(twox_64_concat) OrgId, hasher(blake2_128_concat) T::AccountId => Option<BalanceOf<T>>;
+		/// Map whether account is in or out, and the organization it entered for
+		pub Entered get(fn entered): map hasher(blake2_128_concat) T::AccountId => Option<(OrgId, T::Moment)>;
+		/// Storage key of the last entered account checked for its maximum shift duration, the
+		/// next block carries on after it
+		pub ExitScanKey get(fn exit_scan_key): Option<Vec<u8>>;
+		/// Breaks taken by an account during its current shift
+		pub Breaks get(fn breaks): map hasher(blake2_128_concat) T::AccountId => Vec<BreakRecord<T::Moment>>;
+		/// Break an account is on, when it started and whether it is paid
+		pub OnBreak get(fn on_break): map hasher(blake2_128_concat) T::AccountId => Option<(T::Moment, bool)>;
+		/// Override of the maximum shift duration for an account
+		pub MaxShiftDurations get(fn max_shift_durations):
+			double_map hasher(twox_64_concat) OrgId, hasher(blake2_128_concat) T::AccountId => Option<T::Moment>;
+		/// Store every shift worked by an account, by shift index
+		pub Timesheets get(fn timesheets):
+			double_map hasher(blake2_128_concat) T::AccountId, hasher(twox_64_concat) u32 => Option<ShiftRecord<T::AccountId, T::Moment, BalanceOf<T>>>;
+		/// Number of shifts recorded for an account, which is also the index of the next shift
+		pub ShiftCount get(fn shift_count): map hasher(blake2_128_concat) T::AccountId => u32;
+		/// Accounts with credit waiting to be paid and the organization paying them, by queue position
+		pub PayrollQueue get(fn payroll_queue): map hasher(twox_64_concat) u32 => Option<(OrgId, T::AccountId)>;
+		/// Queue position of an account waiting to be paid by an organization
+		pub PayrollQueued get(fn payroll_queued):
+			double_map hasher(twox_64_concat) OrgId, hasher(blake2_128_concat) T::AccountId => Option<u32>;
+		/// Queue position of the next account to be paid
+		pub PayrollQueueHead get(fn payroll_queue_head): u32;
+		/// Queue position for the next account to be queued
+		pub PayrollQueueTail get(fn payroll_queue_tail): u32;
+		/// Queue position at which the payroll in progress ends
+		pub PayrollEnd get(fn payroll_end): Option<u32>;
+		/// Total credit owed to creditors by an organization
+		pub TotalOutstanding get(fn total_outstanding): map hasher(twox_64_concat) OrgId => BalanceOf<T>;
+		/// Total credit of the pending shifts of an organization, owed once they are approved
+		pub PendingCredit get(fn pending_credit): map hasher(twox_64_concat) OrgId => BalanceOf<T>;
+		/// How the payroll pays the creditors of an organization when underfunded
+		pub Settlement get(fn settlement): map hasher(twox_64_concat) OrgId => SettlementMode;
+		/// Number of payroll runs started, the last of which may still be in progress
+		pub PayrollRun get(fn payroll_run): u32;
+		/// Fraction of its credit each creditor of an organization is paid by a payroll run, `None`
+		/// when paid in full, worked out when the run pays the first creditor of the organization
+		pub PayrollRatios get(fn payroll_ratios): map hasher(twox_64_concat) OrgId => Option<(u32, Option<Perbill>)>;
+		/// Block up to which the salary of a salaried account has been credited
+		pub SalaryAccruedAt get(fn salary_accrued_at):
+			double_map hasher(twox_64_concat) OrgId, hasher(blake2_128_concat) T::AccountId => Option<T::BlockNumber>;
+		/// Fraction of a unit of credit earned by an account but not credited yet
+		pub CreditRemainders get(fn credit_remainders):
+			double_map hasher(twox_64_concat) OrgId, hasher(blake2_128_concat) T::AccountId => FixedU128;
+		/// Store a rate change for an account waiting to be accepted
+		pub RateProposals get(fn rate_proposals):
+			double_map hasher(twox_64_concat) OrgId, hasher(blake2_128_concat) T::AccountId => Option<RateProposal<T::AccountId, BalanceOf<T>, T::BlockNumber>>;
+		/// Multipliers for overtime, night and weekend hours of an organization, time is paid at the
+		/// plain rate if not set
+		pub Policy get(fn pay_policy): map hasher(twox_64_concat) OrgId => Option<PayPolicy<T::Moment>>;
+		/// Store the projects time is billed to, by project id
+		pub Projects get(fn projects): map hasher(twox_64_concat) ProjectId => Option<Project<T::Moment, BalanceOf<T>>>;
+		/// Number of projects created, which is also the id of the next project
+		pub ProjectCount get(fn project_count): ProjectId;
+		/// Map whether an account is allowed to work on a project
+		pub ProjectWorkers get(fn project_workers):
+			double_map hasher(twox_64_concat) ProjectId, hasher(blake2_128_concat) T::AccountId => bool;
+		/// The project an account entered against
+		pub ActiveProject get(fn active_project): map hasher(blake2_128_concat) T::AccountId => Option<ProjectId>;
+		/// Time worked by an account for an organization in its latest day and week
+		pub WorkedTimes get(fn worked_times):
+			double_map hasher(twox_64_concat) OrgId, hasher(blake2_128_concat) T::AccountId => WorkedTime<T::Moment>;
+		/// Number of blocks the shifts of an organization wait for review before they are approved
+		/// automatically, shifts are approved when they are closed if not set
+		pub ApprovalWindows get(fn approval_window): map hasher(twox_64_concat) OrgId => Option<T::BlockNumber>;
+		/// Shifts waiting for review, by the block they are approved automatically at
+		pub PendingApprovals get(fn pending_approvals):
+			map hasher(twox_64_concat) T::BlockNumber => Vec<(T::AccountId, u32)>;
+		/// Store the time-clock devices, by the account of their key
+		pub Devices get(fn devices): map hasher(blake2_128_concat) T::AccountId => Option<Device>;
+		/// Map whether an account is allowed to punch on the devices of a site of an organization
+		pub SiteWorkers get(fn site_workers):
+			double_map hasher(twox_64_concat) (OrgId, SiteId), hasher(blake2_128_concat) T::AccountId => bool;
+		/// Number of punches made by an account on devices, which is also the nonce of its next punch
+		pub PunchNonces get(fn punch_nonce): map hasher(blake2_128_concat) T::AccountId => u32;
+		/// Map whether a worker is assigned to a supervisor of an organization
+		pub SupervisedWorkers get(fn supervised_workers):
+			double_map hasher(blake2_128_concat) (OrgId, T::AccountId), hasher(blake2_128_concat) T::AccountId => bool;
+		/// The supervisor who entered an account for its current shift
+		pub EnteredBy get(fn entered_by): map hasher(blake2_128_concat) T::AccountId => Option<T::AccountId>;
+		/// Shifts recorded by a supervisor which the worker disputes, with the hash of the reason
+		pub Disputes get(fn disputes):
+			double_map hasher(blake2_128_concat) T::AccountId, hasher(twox_64_concat) u32 => Option<T::Hash>;
+		/// Random seed the challenge codes of the sites are derived from, by block
+		pub ChallengeSeeds get(fn challenge_seed): map hasher(twox_64_concat) T::BlockNumber => Option<T::Hash>;
+		/// Oldest challenge code an organization accepts to enter or exit its accounts, in blocks,
+		/// no code is needed if not set
+		pub ChallengeAges get(fn challenge_age): map hasher(twox_64_concat) OrgId => Option<T::BlockNumber>;
+		/// Number of times an account has failed a challenge of an organization
+		pub FailedChallenges get(fn failed_challenges):
+			double_map hasher(twox_64_concat) OrgId, hasher(blake2_128_concat) T::AccountId => u32;
+		/// Store the corrections requested by an account, by the index of the shift corrected and
+		/// the number of the correction, every correction of a shift is kept as its audit trail
+		pub Corrections get(fn corrections):
+			double_map hasher(blake2_128_concat) T::AccountId, hasher(twox_64_concat) (u32, u32) => Option<CorrectionRequest<T::Moment, T::Hash>>;
+		/// Number of corrections requested for a shift of an account, which is also the number of
+		/// the next correction
+		pub CorrectionCount get(fn correction_count):
+			double_map hasher(blake2_128_concat) T::AccountId, hasher(twox_64_concat) u32 => u32;
+		/// The storage layout in use
+		StorageVersion get(fn storage_version): Releases;
 	}
 	add_extra_genesis {
 		build(|_config| {
 			let admin = T::Hashing::hash("timekeeper-administrator".as_bytes());
-			let registrar = T::Hashing::hash("timekeeper-registrar".as_bytes());
-			// Create the role "administrator" and "registrar", setting the role "admin" as admin of role "registrar"
+			// Create the role "administrator", which creates organizations
 			// We would need to set an account to the "administrator" role which we would do with a sudo call
-			// These roles then can be assigned users with calls on the access pallet
+			// Each organization then has its own "administrator" and "registrar" roles
 			AdminRole::<T>::put(admin);
-			RegistrarRole::<T>::put(registrar);
+			StorageVersion::put(Releases::V2);
 		})
 	}
 }
 
+/// The role allowed to create organizations, to gate calls with `access::EnsureRole`
+pub struct TimekeeperAdminRole<T>(frame_support::sp_std::marker::PhantomData<T>);
+
+impl<T: Trait> Get<T::Hash> for TimekeeperAdminRole<T> {
+	fn get() -> T::Hash {
+		AdminRole::<T>::get()
+	}
+}
+
 decl_event!(
 	pub enum Event<T> 
 	where AccountId = <T as frame_system::Trait>::AccountId,
-	Balance = BalanceOf<T> {
-		/// An account has been registered with an hourly rate
-		/// [account, value]
-		AccountRegistered(AccountId, Option<Balance>),
-		AccountWithdrawl(AccountId, Balance),
-		AccountUpdated(AccountId, Option<Balance>),
-		AccountEntered(AccountId),
-		AccountExited(AccountId),
-		Deposit(Balance),
+	Balance = BalanceOf<T>,
+	Hash = <T as frame_system::Trait>::Hash,
+	Rate = RateSpec<BalanceOf<T>>,
+	Moment = <T as timestamp::Trait>::Moment,
+	BlockNumber = <T as frame_system::Trait>::BlockNumber {
+		/// An organization has been created
+		/// [caller, organization, administrator]
+		OrganizationCreated(AccountId, OrgId, AccountId),
+		/// An account has been registered with an organization with a rate
+		/// [organization, account, rate]
+		AccountRegistered(OrgId, AccountId, Option<Rate>),
+		/// An account has withdrawn some of its credit
+		/// [organization, account, amount, remaining credit]
+		Withdrawn(OrgId, AccountId, Balance, Balance),
+		AccountUpdated(OrgId, AccountId, Option<Rate>),
+		AccountEntered(OrgId, AccountId),
+		AccountExited(OrgId, AccountId),
+		/// The pot of an organization has been funded
+		/// [organization, amount]
+		Deposit(OrgId, Balance),
+		/// The pot of an organization holds less than the credit owed to its creditors
+		/// [organization, pot balance, total outstanding]
+		Underfunded(OrgId, Balance, Balance),
+		/// An administrator has reclaimed funds above the credit owed to creditors
+		/// [caller, organization, amount]
+		SurplusReclaimed(AccountId, OrgId, Balance),
+		/// Salary has been credited to a salaried account
+		/// [organization, account, amount]
+		SalaryAccrued(OrgId, AccountId, Balance),
+		/// The settlement mode of the payroll of an organization has been set
+		/// [organization, mode]
+		SettlementModeSet(OrgId, SettlementMode),
+		/// The payroll in progress pays every creditor of an organization the same fraction of its credit
+		/// [organization, ratio]
+		PartialPayroll(OrgId, Perbill),
 		AdminSetup(AccountId),
+		/// An account has been exited after reaching its maximum shift duration
+		/// [organization, account, duration]
+		AutoExited(OrgId, AccountId, Moment),
+		/// The maximum shift duration for an account has been overridden
+		/// [organization, account, duration]
+		MaxShiftDurationUpdated(OrgId, AccountId, Option<Moment>),
+		/// A shift has been recorded in the timesheet of an account
+		/// [account, shift index]
+		ShiftRecorded(AccountId, u32),
+		/// A payroll run has started
+		/// [block number, accounts to pay]
+		PayrollStarted(BlockNumber, u32),
+		/// The payroll run in progress has paid every account queued
+		PayrollCompleted,
+		/// An account has been paid its credit by the payroll
+		/// [organization, account, amount]
+		AccountPaid(OrgId, AccountId, Balance),
+		/// The payroll failed to pay an account, the credit is kept for the next run
+		/// [organization, account, amount]
+		PaymentFailed(OrgId, AccountId, Balance),
+		/// A rate change has been proposed for an account
+		/// [proposer, organization, account, rate]
+		RateProposed(AccountId, OrgId, AccountId, Option<Rate>),
+		/// A rate change has been accepted and applied to an account
+		/// [caller, organization, account, rate]
+		RateAccepted(AccountId, OrgId, AccountId, Option<Rate>),
+		/// A rate change for an account has been rejected
+		/// [caller, organization, account]
+		RateRejected(AccountId, OrgId, AccountId),
+		/// A rate change for an account has been cancelled
+		/// [caller, organization, account]
+		RateProposalCancelled(AccountId, OrgId, AccountId),
+		/// The pay policy of an organization has been set or removed by an administrator
+		/// [caller, organization]
+		PayPolicySet(AccountId, OrgId),
+		/// An account has started a break
+		/// [account, paid]
+		BreakStarted(AccountId, bool),
+		/// An account has ended a break
+		/// [account, duration]
+		BreakEnded(AccountId, Moment),
+		/// A project has been created by an administrator
+		/// [caller, organization, project]
+		ProjectCreated(AccountId, OrgId, ProjectId),
+		/// The budgets of a project have been updated
+		/// [caller, project]
+		ProjectBudgetUpdated(AccountId, ProjectId),
+		/// An account has been allowed to work on a project
+		/// [project, account]
+		ProjectWorkerAdded(ProjectId, AccountId),
+		/// An account is no longer allowed to work on a project
+		/// [project, account]
+		ProjectWorkerRemoved(ProjectId, AccountId),
+		/// A project has used 80% of its time or credit budget
+		/// [project]
+		BudgetWarning(ProjectId),
+		/// A project has used all of its time or credit budget
+		/// [project]
+		BudgetExhausted(ProjectId),
+		/// The approval window of an organization has been set or removed by an administrator
+		/// [caller, organization, blocks]
+		ApprovalWindowSet(AccountId, OrgId, Option<BlockNumber>),
+		/// A shift is waiting for review
+		/// [account, shift index, block it is approved automatically at]
+		ShiftPending(AccountId, u32, BlockNumber),
+		/// The time credited for a pending shift has been changed by an approver
+		/// [caller, account, shift index, duration]
+		ShiftEdited(AccountId, AccountId, u32, Moment),
+		/// A shift has been approved by an approver and its credit added to the account
+		/// [caller, account, shift index]
+		ShiftApproved(AccountId, AccountId, u32),
+		/// A shift nobody reviewed in time has been approved
+		/// [account, shift index]
+		ShiftAutoApproved(AccountId, u32),
+		/// A shift has been rejected by an approver and its credit won't be paid
+		/// [caller, account, shift index, reason code]
+		ShiftRejected(AccountId, AccountId, u32, u8),
+		/// An account has requested a correction of the enter and exit times of a shift
+		/// [account, shift index, start, end, justification]
+		CorrectionRequested(AccountId, u32, Moment, Moment, Hash),
+		/// A correction has been approved and the shift recalculated
+		/// [caller, account, shift index]
+		CorrectionApproved(AccountId, AccountId, u32),
+		/// A correction has been denied and the shift left unchanged
+		/// [caller, account, shift index]
+		CorrectionDenied(AccountId, AccountId, u32),
+		/// A time-clock device has been registered at a site of an organization
+		/// [caller, organization, site, device]
+		DeviceRegistered(AccountId, OrgId, SiteId, AccountId),
+		/// A time-clock device has been activated or deactivated
+		/// [caller, device, active]
+		DeviceStatusSet(AccountId, AccountId, bool),
+		/// An account has been allowed or no longer allowed to punch at a site
+		/// [caller, organization, site, account, allowed]
+		SiteWorkerSet(AccountId, OrgId, SiteId, AccountId, bool),
+		/// A worker has punched on a time-clock device
+		/// [device, worker, kind]
+		Punched(AccountId, AccountId, PunchKind),
+		/// The oldest challenge code an organization accepts has been set by an administrator
+		/// [caller, organization, blocks]
+		ChallengeAgeSet(AccountId, OrgId, Option<BlockNumber>),
+		/// An account has failed to give a valid challenge code to enter or exit
+		/// [organization, account]
+		ChallengeFailed(OrgId, AccountId),
+		/// An offline punch has been refused, the other punches of the batch are still applied
+		/// [worker, nonce, error]
+		OfflinePunchRejected(AccountId, u32, DispatchError),
+		/// A worker has been assigned to or removed from a supervisor
+		/// [caller, organization, supervisor, worker, assigned]
+		WorkerAssigned(AccountId, OrgId, AccountId, AccountId, bool),
+		/// A supervisor has entered a worker
+		/// [supervisor, organization, worker]
+		EnteredFor(AccountId, OrgId, AccountId),
+		/// A supervisor has exited a worker
+		/// [supervisor, organization, worker]
+		ExitedFor(AccountId, OrgId, AccountId),
+		/// A worker disputes a shift recorded by a supervisor
+		/// [account, shift index, reason]
+		ShiftDisputed(AccountId, u32, Hash),
+		/// An approver or administrator has dismissed the dispute of a shift
+		/// [who, account, shift index]
+		DisputeDismissed(AccountId, AccountId, u32),
 	}
 );
 
@@ -79,6 +684,48 @@ decl_error! {
 		ErrorRegistrarRoleRequired,
 		ErrorAdminRoleRequired,
 		ErrorAlreadyRegistered,
+		ErrorAdminOrAccountHolderRequired,
+		ErrorAccountHolderRequired,
+		ErrorProposerRequired,
+		ErrorRateProposalExists,
+		ErrorRateProposalNotFound,
+		ErrorRateProposalExpired,
+		ErrorUnderfunded,
+		ErrorInsufficientSurplus,
+		ErrorInvalidPayPolicy,
+		FailedToStartBreak,
+		FailedToEndBreak,
+		ErrorTooManyBreaks,
+		ErrorProjectNotFound,
+		ErrorNotProjectWorker,
+		ErrorOrganizationNotFound,
+		ErrorApproverRoleRequired,
+		ErrorShiftNotFound,
+		ErrorShiftNotPending,
+		ErrorInvalidShiftDuration,
+		ErrorShiftRejected,
+		ErrorInvalidCorrection,
+		ErrorCorrectionExists,
+		ErrorCorrectionNotFound,
+		ErrorCorrectionNotPending,
+		ErrorDeviceExists,
+		ErrorDeviceNotFound,
+		ErrorDeviceInactive,
+		ErrorNotSiteWorker,
+		ErrorInvalidPunchSignature,
+		ErrorInvalidPunchNonce,
+		ErrorInvalidChallengeAge,
+		ErrorChallengeFailed,
+		ErrorTooManyPunches,
+		ErrorPunchInFuture,
+		ErrorPunchTooOld,
+		ErrorPunchOutOfOrder,
+		ErrorSupervisorRoleRequired,
+		ErrorWorkerNotAssigned,
+		ErrorNotRecordedBySupervisor,
+		ErrorShiftDisputed,
+		ErrorInvalidApprovalWindow,
+		ErrorShiftNotDisputed,
 	}
 }
 
@@ -86,149 +733,1635 @@ decl_module! {
 	pub struct Module<T: Trait> for enum Call where origin: T::Origin {
 		type Error = Error<T>;
 
+		const MaxShiftDuration: T::Moment = T::MaxShiftDuration::get();
+		const PayrollPeriod: T::BlockNumber = T::PayrollPeriod::get();
+		const PayrollBatchSize: u32 = T::PayrollBatchSize::get();
+		const AutoExitBatchSize: u32 = T::AutoExitBatchSize::get();
+		const RateProposalExpiry: T::BlockNumber = T::RateProposalExpiry::get();
+		const AutoBreakAfter: T::Moment = T::AutoBreakAfter::get();
+		const AutoBreakDuration: T::Moment = T::AutoBreakDuration::get();
+		const MaxChallengeAge: T::BlockNumber = T::MaxChallengeAge::get();
+		const MaxBackdating: T::Moment = T::MaxBackdating::get();
+		const MaxOfflinePunches: u32 = T::MaxOfflinePunches::get();
+
 		fn deposit_event() = default;
 
-		#[weight = 10_000 + T::DbWeight::get().writes(1)]
+		fn on_initialize(n: T::BlockNumber) -> Weight {
+			let mut weight = Self::rotate_challenge_seed(n);
+			weight = weight.saturating_add(Self::exit_overdue_accounts());
+			let period = T::PayrollPeriod::get();
+			if !period.is_zero() && (n % period).is_zero() {
+				weight = weight.saturating_add(Self::start_payroll(n));
+			}
+			weight = weight.saturating_add(Self::approve_overdue_shifts(n));
+			weight.saturating_add(Self::process_payroll())
+		}
+
+		fn on_runtime_upgrade() -> Weight {
+			if Self::storage_version() == Releases::V1 {
+				Self::migrate_to_v2()
+			} else {
+				0
+			}
+		}
+
+		/// Make `account` a timekeeper administrator, whitelisting it so it can sign calls
+		#[weight = 10_000 + T::DbWeight::get().writes(2)]
 		pub fn setup(origin, account: T::AccountId) -> dispatch::DispatchResult {
 			let _ = ensure_root(origin)?;
-			<access::Module<T>>::set_admin_for_role(RegistrarRole::<T>::get(), AdminRole::<T>::get()).expect("failed creating roles, make sure these roles aren't in use");
 			<access::Module<T>>::add_account_to_role(AdminRole::<T>::get(), account.clone())?;
+			let _ = <access::Module<T>>::add_account_to_role(T::WhitelistRole::get(), account.clone());
 			Self::deposit_event(RawEvent::AdminSetup(account));
 			Ok(())
 		}
-	
+
+		/// Create an organization with its own pot and roles, making `admin` its first administrator
+		/// and whitelisting it
+		#[weight = 10_000 + T::DbWeight::get().writes(12)]
+		pub fn create_organization(origin, admin: T::AccountId) -> dispatch::DispatchResult {
+			let who = access::EnsureRole::<T, TimekeeperAdminRole<T>>::ensure_origin(origin)
+				.map_err(|_| Error::<T>::ErrorAdminRoleRequired)?;
+			let org = Self::organization_count();
+			let organization = Organization {
+				admin_role: T::Hashing::hash_of(&("timekeeper-administrator", org)),
+				registrar_role: T::Hashing::hash_of(&("timekeeper-registrar", org)),
+				approver_role: T::Hashing::hash_of(&("timekeeper-approver", org)),
+				supervisor_role: T::Hashing::hash_of(&("timekeeper-supervisor", org)),
+			};
+			Self::set_up_roles(&organization)?;
+			<access::Module<T>>::add_account_to_role(organization.admin_role, admin.clone())?;
+			let _ = <access::Module<T>>::add_account_to_role(T::WhitelistRole::get(), admin.clone());
+			Organizations::<T>::insert(org, organization);
+			OrganizationCount::put(org.saturating_add(1));
+			Self::deposit_event(RawEvent::OrganizationCreated(who, org, admin));
+			Ok(())
+		}
+
+		/// Register an account with an organization, whitelisting it so it can enter and exit
+		#[weight = 10_000 + T::DbWeight::get().reads_writes(2, 4)]
+		pub fn register_account(origin, org: OrgId, account: T::AccountId, rate: Option<RateSpec<BalanceOf<T>>>) -> dispatch::DispatchResult {
+			let who = ensure_signed(origin)?;
+			let organization = Self::organizations(org).ok_or(Error::<T>::ErrorOrganizationNotFound)?;
+			ensure!(!Rates::<T>::contains_key(org, &account), Error::<T>::ErrorAlreadyRegistered);
+			ensure!(<access::Module<T>>::has_role(organization.registrar_role, who), Error::<T>::ErrorRegistrarRoleRequired);
+			// Accounts registered before are already whitelisted
+			let _ = <access::Module<T>>::add_account_to_role(T::WhitelistRole::get(), account.clone());
+			Self::set_rate(org, &account, rate);
+			Self::deposit_event(RawEvent::AccountRegistered(org, account, rate));
+			Ok(())
+		}
+
+		/// Propose a new rate for an account, the proposal has to be signed by an administrator or
+		/// the account holder and is applied once the other party accepts it
 		#[weight = 10_000 + T::DbWeight::get().writes(1)]
-		pub fn register_account(origin, account: T::AccountId, rate: Option<BalanceOf<T>>) -> dispatch::DispatchResult {
+		pub fn update_rate_for_account(origin, org: OrgId, account: T::AccountId, rate: Option<RateSpec<BalanceOf<T>>>) -> dispatch::DispatchResult {
 			let who = ensure_signed(origin)?;
-			ensure!(!Rates::<T>::contains_key(&account), Error::<T>::ErrorAlreadyRegistered);
-			ensure!(<access::Module<T>>::has_role(RegistrarRole::<T>::get(), who), Error::<T>::ErrorRegistrarRoleRequired);
-			Rates::<T>::mutate_exists(&account, |r| *r = rate);
-			Self::deposit_event(RawEvent::AccountRegistered(account, rate));
+			Self::check_if_registered(org, &account)?;
+			ensure!(who == account || Self::is_admin(org, &who), Error::<T>::ErrorAdminOrAccountHolderRequired);
+			if let Some(proposal) = Self::rate_proposals(org, &account) {
+				ensure!(Self::is_expired(&proposal), Error::<T>::ErrorRateProposalExists);
+			}
+			let expires = <frame_system::Module<T>>::block_number().saturating_add(T::RateProposalExpiry::get());
+			RateProposals::<T>::insert(org, &account, RateProposal { proposer: who.clone(), rate, expires });
+			Self::deposit_event(RawEvent::RateProposed(who, org, account, rate));
 			Ok(())
 		}
 
+		/// Accept the rate proposed for an account, as the account holder if an administrator
+		/// proposed it or as an administrator if the account holder proposed it
+		#[weight = 10_000 + T::DbWeight::get().writes(2)]
+		pub fn accept_rate(origin, org: OrgId, account: T::AccountId) -> dispatch::DispatchResult {
+			let who = ensure_signed(origin)?;
+			let proposal = Self::rate_proposals(org, &account).ok_or(Error::<T>::ErrorRateProposalNotFound)?;
+			ensure!(!Self::is_expired(&proposal), Error::<T>::ErrorRateProposalExpired);
+			Self::ensure_counterparty(org, &who, &account, &proposal)?;
+			RateProposals::<T>::remove(org, &account);
+			Self::set_rate(org, &account, proposal.rate);
+			Self::deposit_event(RawEvent::RateAccepted(who, org, account.clone(), proposal.rate));
+			Self::deposit_event(RawEvent::AccountUpdated(org, account, proposal.rate));
+			Ok(())
+		}
+
+		/// Reject the rate proposed for an account, as the party which has to accept it
 		#[weight = 10_000 + T::DbWeight::get().writes(1)]
-		pub fn update_rate_for_account(origin, account: T::AccountId, rate: Option<BalanceOf<T>>) -> dispatch::DispatchResult {
-			// let who = ensure_signed(origin)?;
-			// TODO who here has to be a transaction signed by an administrator and the account holder
-			Rates::<T>::mutate_exists(&account, |r| *r = rate);
-			// Emit an event.
-			Self::deposit_event(RawEvent::AccountUpdated(account, rate));
-			// Return a successful DispatchResult
+		pub fn reject_rate(origin, org: OrgId, account: T::AccountId) -> dispatch::DispatchResult {
+			let who = ensure_signed(origin)?;
+			let proposal = Self::rate_proposals(org, &account).ok_or(Error::<T>::ErrorRateProposalNotFound)?;
+			Self::ensure_counterparty(org, &who, &account, &proposal)?;
+			RateProposals::<T>::remove(org, &account);
+			Self::deposit_event(RawEvent::RateRejected(who, org, account));
 			Ok(())
 		}
 
+		/// Cancel the rate proposed for an account, as the proposer or by anyone once expired
 		#[weight = 10_000 + T::DbWeight::get().writes(1)]
-		pub fn deposit(origin, value: Option<BalanceOf<T>>) -> dispatch::DispatchResult {
+		pub fn cancel_rate_proposal(origin, org: OrgId, account: T::AccountId) -> dispatch::DispatchResult {
 			let who = ensure_signed(origin)?;
+			let proposal = Self::rate_proposals(org, &account).ok_or(Error::<T>::ErrorRateProposalNotFound)?;
+			ensure!(proposal.proposer == who || Self::is_expired(&proposal), Error::<T>::ErrorProposerRequired);
+			RateProposals::<T>::remove(org, &account);
+			Self::deposit_event(RawEvent::RateProposalCancelled(who, org, account));
+			Ok(())
+		}
+
+		/// Fund the pot of an organization which pays its creditors
+		#[weight = 10_000 + T::DbWeight::get().writes(1)]
+		pub fn deposit(origin, org: OrgId, value: BalanceOf<T>) -> dispatch::DispatchResult {
+			let who = ensure_signed(origin)?;
+			ensure!(Organizations::<T>::contains_key(org), Error::<T>::ErrorOrganizationNotFound);
 			T::Currency::transfer(
 				&who,
-				&Self::account_id(),
-				value.unwrap(),
+				&Self::account_id(org),
+				value,
 				ExistenceRequirement::AllowDeath
 			)?;
 			// Emit an event.
-			Self::deposit_event(RawEvent::Deposit(value.unwrap()));
+			Self::deposit_event(RawEvent::Deposit(org, value));
 			// Return a successful DispatchResult
 			Ok(())
 		}
 
-		#[weight = (10_000, DispatchClass::Normal, Pays::No)]
-		pub fn withdraw(origin, amount: BalanceOf<T>) -> dispatch::DispatchResult {
-			let who = ensure_signed(origin)?;
-			
-			Creditors::<T>::try_mutate_exists(who.clone(), |credit| -> dispatch::DispatchResult {
-				let credit = credit.take().ok_or(Error::<T>::FailedCredit)?;
-				ensure!(credit >= amount.into(), Error::<T>::FailedInsufficientCredit);
-				
-				with_transaction_result(|| {
-					
-					T::Currency::transfer(
-						&Self::account_id(),
-						&who,
-						amount,
-						ExistenceRequirement::AllowDeath
-					)?;
-
-					Self::deposit_event(RawEvent::AccountWithdrawl(who, amount));
-				
-					Ok(())
-				})
-			})?;
+		/// Transfer funds of the pot of an organization above what it owes to creditors to the
+		/// administrator
+		#[weight = 10_000 + T::DbWeight::get().writes(1)]
+		pub fn reclaim_surplus(origin, org: OrgId, amount: BalanceOf<T>) -> dispatch::DispatchResult {
+			let who = ensure_signed(origin)?;
+			Self::ensure_admin(org, &who)?;
+			ensure!(amount <= Self::surplus(org), Error::<T>::ErrorInsufficientSurplus);
+			T::Currency::transfer(
+				&Self::account_id(org),
+				&who,
+				amount,
+				ExistenceRequirement::AllowDeath
+			)?;
+			Self::deposit_event(RawEvent::SurplusReclaimed(who, org, amount));
+			Ok(())
+		}
 
+		/// Set how the payroll pays the creditors of an organization when its pot is underfunded
+		#[weight = 10_000 + T::DbWeight::get().writes(1)]
+		pub fn set_settlement_mode(origin, org: OrgId, mode: SettlementMode) -> dispatch::DispatchResult {
+			let who = ensure_signed(origin)?;
+			Self::ensure_admin(org, &who)?;
+			Settlement::insert(org, mode);
+			Self::deposit_event(RawEvent::SettlementModeSet(org, mode));
 			Ok(())
 		}
 
+		/// Withdraw part of the credit of the caller with an organization, the rest stays in credit
+		#[weight = (10_000, DispatchClass::Normal, Pays::No)]
+		pub fn withdraw(origin, org: OrgId, amount: BalanceOf<T>) -> dispatch::DispatchResult {
+			let who = ensure_signed(origin)?;
+			Self::do_withdraw(org, who, amount)
+		}
+
+		/// Withdraw all the credit of the caller with an organization
 		#[weight = (10_000, DispatchClass::Normal, Pays::No)]
-		pub fn enter_account(origin) -> dispatch::DispatchResult {
+		pub fn withdraw_all(origin, org: OrgId) -> dispatch::DispatchResult {
 			let who = ensure_signed(origin)?;
-			Self::check_if_registered(&who)?;
+			let credit = Self::creditors(org, &who).ok_or(Error::<T>::FailedCredit)?;
+			Self::do_withdraw(org, who, credit)
+		}
 
-			match Self::entered(&who) {
-				Some(_) => {
-					// Already entered
-					Err(Error::<T>::FailedToEnter)?
+		/// Enter the account for an organization it is registered with. The challenge code of a
+		/// site is needed if the organization requires one
+		#[weight = (10_000, DispatchClass::Normal, Pays::No)]
+		pub fn enter_account(origin, org: OrgId, challenge: Option<Challenge<T::BlockNumber>>) -> dispatch::DispatchResult {
+			let who = ensure_signed(origin)?;
+			Self::check_if_registered(org, &who)?;
+			Self::check_challenge(org, &who, challenge)?;
+			Self::do_enter(org, who, None, <timestamp::Module<T>>::get())
+		}
+
+		/// Enter the account against a project it is allowed to work on, the shift is billed to
+		/// the project
+		#[weight = (10_000, DispatchClass::Normal, Pays::No)]
+		pub fn enter_project(origin, project: ProjectId, challenge: Option<Challenge<T::BlockNumber>>) -> dispatch::DispatchResult {
+			let who = ensure_signed(origin)?;
+			let org = Self::projects(project).ok_or(Error::<T>::ErrorProjectNotFound)?.org;
+			Self::check_if_registered(org, &who)?;
+			ensure!(Self::project_workers(project, &who), Error::<T>::ErrorNotProjectWorker);
+			Self::check_challenge(org, &who, challenge)?;
+			Self::do_enter(org, who, Some(project), <timestamp::Module<T>>::get())
+		}
+
+		/// Exit the account. The challenge code of a site is needed if the organization the
+		/// account entered for requires one
+		#[weight = (10_000, DispatchClass::Normal, Pays::No)]
+		pub fn exit_account(origin, challenge: Option<Challenge<T::BlockNumber>>) -> dispatch::DispatchResult {
+			let who = ensure_signed(origin)?;
+			if let Some((org, _)) = Self::entered(&who) {
+				Self::check_challenge(org, &who, challenge)?;
+			}
+			Self::do_exit(who, None)
+		}
+
+		/// Assign a worker registered with an organization to one of its supervisors, or remove
+		/// the assignment
+		#[weight = 10_000 + T::DbWeight::get().writes(1)]
+		pub fn assign_worker(origin, org: OrgId, supervisor: T::AccountId, worker: T::AccountId, assigned: bool) -> dispatch::DispatchResult {
+			let who = ensure_signed(origin)?;
+			Self::ensure_admin(org, &who)?;
+			if assigned {
+				Self::check_if_registered(org, &worker)?;
+				SupervisedWorkers::<T>::insert((org, supervisor.clone()), &worker, true);
+			} else {
+				SupervisedWorkers::<T>::remove((org, supervisor.clone()), &worker);
+			}
+			Self::deposit_event(RawEvent::WorkerAssigned(who, org, supervisor, worker, assigned));
+			Ok(())
+		}
+
+		/// Enter a worker assigned to the caller, as a supervisor of the organization
+		#[weight = 10_000 + T::DbWeight::get().writes(3)]
+		pub fn enter_for(origin, org: OrgId, worker: T::AccountId) -> dispatch::DispatchResult {
+			let who = ensure_signed(origin)?;
+			Self::ensure_supervisor(org, &who, &worker)?;
+			Self::check_if_registered(org, &worker)?;
+			Self::do_enter(org, worker.clone(), None, <timestamp::Module<T>>::get())?;
+			EnteredBy::<T>::insert(&worker, &who);
+			Self::deposit_event(RawEvent::EnteredFor(who, org, worker));
+			Ok(())
+		}
+
+		/// Exit a worker assigned to the caller, as a supervisor of the organization the worker
+		/// entered for
+		#[weight = 10_000 + T::DbWeight::get().writes(6)]
+		pub fn exit_for(origin, worker: T::AccountId) -> dispatch::DispatchResult {
+			let who = ensure_signed(origin)?;
+			let (org, _) = Self::entered(&worker).ok_or(Error::<T>::FailedToExit)?;
+			Self::ensure_supervisor(org, &who, &worker)?;
+			Self::do_exit(worker.clone(), Some(who.clone()))?;
+			Self::deposit_event(RawEvent::ExitedFor(who, org, worker));
+			Ok(())
+		}
+
+		/// Dispute a pending shift of the caller entered or exited by a supervisor, giving the
+		/// hash of the reason. Such shifts wait for review even when the organization has no
+		/// approval window. A disputed shift isn't approved automatically, an approver reviews it
+		/// or an approver or administrator dismisses the dispute
+		#[weight = 10_000 + T::DbWeight::get().writes(1)]
+		pub fn dispute_shift(origin, index: u32, reason: T::Hash) -> dispatch::DispatchResult {
+			let who = ensure_signed(origin)?;
+			let shift = Self::timesheets(&who, index).ok_or(Error::<T>::ErrorShiftNotFound)?;
+			ensure!(shift.entered_by.is_some() || shift.exited_by.is_some(), Error::<T>::ErrorNotRecordedBySupervisor);
+			ensure!(shift.status == ShiftStatus::Pending, Error::<T>::ErrorShiftNotPending);
+			ensure!(!Disputes::<T>::contains_key(&who, index), Error::<T>::ErrorShiftDisputed);
+			Disputes::<T>::insert(&who, index, reason);
+			Self::deposit_event(RawEvent::ShiftDisputed(who, index, reason));
+			Ok(())
+		}
+
+		/// Dismiss the dispute of a pending shift, which is then approved automatically at the end
+		/// of a new approval window, or straight away if the organization has none
+		#[weight = 10_000 + T::DbWeight::get().reads_writes(4, 6)]
+		pub fn dismiss_dispute(origin, account: T::AccountId, index: u32) -> dispatch::DispatchResult {
+			let who = ensure_signed(origin)?;
+			let shift = Self::timesheets(&account, index).ok_or(Error::<T>::ErrorShiftNotFound)?;
+			let organization = Self::organizations(shift.org).ok_or(Error::<T>::ErrorOrganizationNotFound)?;
+			ensure!(who != account && (<access::Module<T>>::has_role(organization.approver_role, who.clone()) || Self::is_admin(shift.org, &who)),
+				Error::<T>::ErrorApproverRoleRequired);
+			ensure!(shift.status == ShiftStatus::Pending, Error::<T>::ErrorShiftNotPending);
+			ensure!(Disputes::<T>::take(&account, index).is_some(), Error::<T>::ErrorShiftNotDisputed);
+			Self::deposit_event(RawEvent::DisputeDismissed(who, account.clone(), index));
+			Self::queue_approval(&account, index, shift);
+			Ok(())
+		}
+
+		/// Require accounts to give the challenge code of a site, shown at most `age` blocks ago,
+		/// to enter and exit for an organization, or no code if `None`
+		#[weight = 10_000 + T::DbWeight::get().writes(1)]
+		pub fn set_challenge_age(origin, org: OrgId, age: Option<T::BlockNumber>) -> dispatch::DispatchResult {
+			let who = ensure_signed(origin)?;
+			Self::ensure_admin(org, &who)?;
+			if let Some(age) = age {
+				ensure!(!T::MaxChallengeAge::get().is_zero() && age <= T::MaxChallengeAge::get(), Error::<T>::ErrorInvalidChallengeAge);
+			}
+			ChallengeAges::<T>::mutate_exists(org, |a| *a = age);
+			Self::deposit_event(RawEvent::ChallengeAgeSet(who, org, age));
+			Ok(())
+		}
+
+		/// Register the key of a time-clock device installed at a site of an organization
+		#[weight = 10_000 + T::DbWeight::get().writes(1)]
+		pub fn register_device(origin, org: OrgId, site: SiteId, key: T::Public) -> dispatch::DispatchResult {
+			let who = ensure_signed(origin)?;
+			Self::ensure_admin(org, &who)?;
+			let device = key.into_account();
+			ensure!(!Devices::<T>::contains_key(&device), Error::<T>::ErrorDeviceExists);
+			Devices::<T>::insert(&device, Device { org, site, active: true });
+			Self::deposit_event(RawEvent::DeviceRegistered(who, org, site, device));
+			Ok(())
+		}
+
+		/// Activate or deactivate a time-clock device, punches on inactive devices are refused
+		#[weight = 10_000 + T::DbWeight::get().writes(1)]
+		pub fn set_device_active(origin, device: T::AccountId, active: bool) -> dispatch::DispatchResult {
+			let who = ensure_signed(origin)?;
+			let mut d = Self::devices(&device).ok_or(Error::<T>::ErrorDeviceNotFound)?;
+			Self::ensure_admin(d.org, &who)?;
+			d.active = active;
+			Devices::<T>::insert(&device, d);
+			Self::deposit_event(RawEvent::DeviceStatusSet(who, device, active));
+			Ok(())
+		}
+
+		/// Allow or stop an account registered with an organization punching on the devices of
+		/// one of its sites
+		#[weight = 10_000 + T::DbWeight::get().writes(1)]
+		pub fn set_site_worker(origin, org: OrgId, site: SiteId, account: T::AccountId, allowed: bool) -> dispatch::DispatchResult {
+			let who = ensure_signed(origin)?;
+			Self::ensure_admin(org, &who)?;
+			if allowed {
+				Self::check_if_registered(org, &account)?;
+				SiteWorkers::<T>::insert((org, site), &account, true);
+			} else {
+				SiteWorkers::<T>::remove((org, site), &account);
+			}
+			Self::deposit_event(RawEvent::SiteWorkerSet(who, org, site, account, allowed));
+			Ok(())
+		}
+
+		/// Enter or exit a worker on a time-clock device. The punch is submitted unsigned and
+		/// carries the signatures of the worker and the device, which are checked when validated.
+		/// Its nonce is used up once the signatures are checked, so it can't be replayed even if
+		/// it fails
+		#[weight = (10_000, DispatchClass::Normal, Pays::No)]
+		pub fn punch(origin, punch: Punch<T::AccountId>, worker_signature: T::Signature, device_signature: T::Signature) -> dispatch::DispatchResult {
+			ensure_none(origin)?;
+			let device = Self::check_punch_signatures(&punch.worker, &punch.device, punch.nonce, &punch.encode(), &worker_signature, &device_signature)?;
+			PunchNonces::<T>::insert(&punch.worker, punch.nonce.saturating_add(1));
+			match punch.kind {
+				PunchKind::Enter => {
+					Self::check_if_registered(device.org, &punch.worker)?;
+					Self::do_enter(device.org, punch.worker.clone(), None, <timestamp::Module<T>>::get())?;
 				},
-				None => {
-					let now = <timestamp::Module<T>>::get();
-					Entered::<T>::mutate_exists(&who, |v| *v = Some(now));
-					// Emit an event.
-					Self::deposit_event(RawEvent::AccountEntered(who));
-					// Return a successful DispatchResult
-					Ok(())
+				PunchKind::Exit => {
+					ensure!(Self::entered(&punch.worker).map(|(org, _)| org) == Some(device.org), Error::<T>::FailedToExit);
+					Self::do_exit(punch.worker.clone(), None)?;
+				},
+			}
+			Self::deposit_event(RawEvent::Punched(punch.device, punch.worker, punch.kind));
+			Ok(())
+		}
+
+		/// Apply a batch of punches recorded by time-clock devices while offline, in order and at
+		/// the times they were recorded. Punches which can't be applied are refused without
+		/// failing the batch
+		#[weight = T::DbWeight::get().reads_writes(5, 6).saturating_mul(punches.len() as Weight).saturating_add(10_000)]
+		pub fn submit_offline_punches(origin, punches: Vec<(OfflinePunch<T::AccountId, T::Moment>, T::Signature, T::Signature)>) -> dispatch::DispatchResult {
+			let _ = ensure_signed(origin)?;
+			ensure!(punches.len() <= T::MaxOfflinePunches::get() as usize, Error::<T>::ErrorTooManyPunches);
+			for (punch, worker_signature, device_signature) in punches {
+				if let Err(e) = Self::apply_offline_punch(&punch, &worker_signature, &device_signature) {
+					Self::deposit_event(RawEvent::OfflinePunchRejected(punch.worker, punch.nonce, e));
 				}
 			}
+			Ok(())
+		}
+
+		/// Create a project of an organization with optional time and credit budgets
+		#[weight = 10_000 + T::DbWeight::get().writes(2)]
+		pub fn create_project(origin, org: OrgId, time_budget: Option<T::Moment>, credit_budget: Option<BalanceOf<T>>) -> dispatch::DispatchResult {
+			let who = ensure_signed(origin)?;
+			Self::ensure_admin(org, &who)?;
+			let project = Self::project_count();
+			Projects::<T>::insert(project, Project {
+				org,
+				time_budget,
+				credit_budget,
+				time: Zero::zero(),
+				credit: Zero::zero(),
+			});
+			ProjectCount::put(project.saturating_add(1));
+			Self::deposit_event(RawEvent::ProjectCreated(who, org, project));
+			Ok(())
+		}
+
+		/// Set the time and credit budgets of a project, keeping the time and credit used so far
+		#[weight = 10_000 + T::DbWeight::get().writes(1)]
+		pub fn set_project_budget(origin, project: ProjectId, time_budget: Option<T::Moment>, credit_budget: Option<BalanceOf<T>>) -> dispatch::DispatchResult {
+			let who = ensure_signed(origin)?;
+			let mut p = Self::projects(project).ok_or(Error::<T>::ErrorProjectNotFound)?;
+			Self::ensure_admin(p.org, &who)?;
+			p.time_budget = time_budget;
+			p.credit_budget = credit_budget;
+			Projects::<T>::insert(project, p);
+			Self::deposit_event(RawEvent::ProjectBudgetUpdated(who, project));
+			Ok(())
 		}
 
+		/// Allow an account registered with the organization of a project to work on it
+		#[weight = 10_000 + T::DbWeight::get().writes(1)]
+		pub fn add_project_worker(origin, project: ProjectId, account: T::AccountId) -> dispatch::DispatchResult {
+			let who = ensure_signed(origin)?;
+			let org = Self::projects(project).ok_or(Error::<T>::ErrorProjectNotFound)?.org;
+			Self::ensure_admin(org, &who)?;
+			Self::check_if_registered(org, &account)?;
+			ProjectWorkers::<T>::insert(project, &account, true);
+			Self::deposit_event(RawEvent::ProjectWorkerAdded(project, account));
+			Ok(())
+		}
+
+		/// Stop an account working on a project, a shift in progress is still billed to it
+		#[weight = 10_000 + T::DbWeight::get().writes(1)]
+		pub fn remove_project_worker(origin, project: ProjectId, account: T::AccountId) -> dispatch::DispatchResult {
+			let who = ensure_signed(origin)?;
+			let org = Self::projects(project).ok_or(Error::<T>::ErrorProjectNotFound)?.org;
+			Self::ensure_admin(org, &who)?;
+			ensure!(Self::project_workers(project, &account), Error::<T>::ErrorNotProjectWorker);
+			ProjectWorkers::<T>::remove(project, &account);
+			Self::deposit_event(RawEvent::ProjectWorkerRemoved(project, account));
+			Ok(())
+		}
+
+		/// Start a break during the current shift, unpaid breaks aren't credited
 		#[weight = (10_000, DispatchClass::Normal, Pays::No)]
-		pub fn exit_account(origin) -> dispatch::DispatchResult {
-			let who = ensure_signed(origin)?;
-			Self::check_if_registered(&who)?;
-			
-			match Self::entered(&who) {
-				Some(timestamp) => {
-					let now = <timestamp::Module<T>>::get();
-					let diff = now - timestamp;
-					Entered::<T>::mutate_exists(&who, |v| *v = None);
-					let rate = Self::rates(&who);
-					match rate {
-						Some(r) => {
-							Creditors::<T>::mutate_exists(&who, |credit| {
-								*credit = Some(credit.unwrap_or(0.into()) + Self::calculate_credit(diff, r));
-							});
-						},
-						_ => ()
-					}
-					
-					// Emit an event.
-					Self::deposit_event(RawEvent::AccountExited(who));
-					// Return a successful DispatchResult
-					Ok(())
+		pub fn start_break(origin, paid: bool) -> dispatch::DispatchResult {
+			let who = ensure_signed(origin)?;
+			ensure!(Self::entered(&who).is_some() && Self::on_break(&who).is_none(), Error::<T>::FailedToStartBreak);
+			ensure!(Self::breaks(&who).len() < MAX_BREAKS, Error::<T>::ErrorTooManyBreaks);
+			let now = <timestamp::Module<T>>::get();
+			OnBreak::<T>::insert(&who, (now, paid));
+			Self::deposit_event(RawEvent::BreakStarted(who, paid));
+			Ok(())
+		}
+
+		/// End the break in progress
+		#[weight = (10_000, DispatchClass::Normal, Pays::No)]
+		pub fn end_break(origin) -> dispatch::DispatchResult {
+			let who = ensure_signed(origin)?;
+			let (start, paid) = OnBreak::<T>::take(&who).ok_or(Error::<T>::FailedToEndBreak)?;
+			let now = <timestamp::Module<T>>::get();
+			Breaks::<T>::mutate(&who, |breaks| breaks.push(BreakRecord { start, end: now, paid }));
+			Self::deposit_event(RawEvent::BreakEnded(who, now.saturating_sub(start)));
+			Ok(())
+		}
+
+		#[weight = 10_000 + T::DbWeight::get().writes(1)]
+		pub fn set_max_shift_duration(origin, org: OrgId, account: T::AccountId, duration: Option<T::Moment>) -> dispatch::DispatchResult {
+			let who = ensure_signed(origin)?;
+			Self::ensure_admin(org, &who)?;
+			Self::check_if_registered(org, &account)?;
+			MaxShiftDurations::<T>::mutate_exists(org, &account, |d| *d = duration);
+			Self::deposit_event(RawEvent::MaxShiftDurationUpdated(org, account, duration));
+			Ok(())
+		}
+
+		/// Set the multipliers an organization pays for overtime, night and weekend hours, or pay
+		/// every hour at the plain rate if `None`. Applies to shifts closed from now on
+		#[weight = 10_000 + T::DbWeight::get().writes(1)]
+		pub fn set_pay_policy(origin, org: OrgId, policy: Option<PayPolicy<T::Moment>>) -> dispatch::DispatchResult {
+			let who = ensure_signed(origin)?;
+			Self::ensure_admin(org, &who)?;
+			if let Some((start, end)) = policy.as_ref().and_then(|p| p.night) {
+				ensure!(start.saturated_into::<u128>() < MILLISECS_PER_DAY && end.saturated_into::<u128>() < MILLISECS_PER_DAY,
+					Error::<T>::ErrorInvalidPayPolicy);
+			}
+			Policy::<T>::mutate_exists(org, |p| *p = policy);
+			Self::deposit_event(RawEvent::PayPolicySet(who, org));
+			Ok(())
+		}
+
+		/// Require the shifts of an organization to be reviewed by an approver before their credit
+		/// can be withdrawn, approving them automatically after `window` blocks. Shifts are
+		/// approved when closed if `None`. The window is at least a block, as shifts are approved
+		/// when a block starts. Applies to shifts closed from now on
+		#[weight = 10_000 + T::DbWeight::get().writes(1)]
+		pub fn set_approval_window(origin, org: OrgId, window: Option<T::BlockNumber>) -> dispatch::DispatchResult {
+			let who = ensure_signed(origin)?;
+			Self::ensure_admin(org, &who)?;
+			ensure!(window.map_or(true, |w| !w.is_zero()), Error::<T>::ErrorInvalidApprovalWindow);
+			ApprovalWindows::<T>::mutate_exists(org, |w| *w = window);
+			Self::deposit_event(RawEvent::ApprovalWindowSet(who, org, window));
+			Ok(())
+		}
+
+		/// Approve a pending shift, adding its credit to the account
+		#[weight = 10_000 + T::DbWeight::get().writes(4)]
+		pub fn approve_shift(origin, account: T::AccountId, index: u32) -> dispatch::DispatchResult {
+			let who = ensure_signed(origin)?;
+			let shift = Self::pending_shift(&who, &account, index)?;
+			Disputes::<T>::remove(&account, index);
+			Self::approve(&account, index, shift);
+			Self::deposit_event(RawEvent::ShiftApproved(who, account, index));
+			Ok(())
+		}
+
+		/// Change the time credited for a pending shift, which can't be more than the time between
+		/// entering and exiting. The credit of the shift and of its segments is scaled to the new
+		/// duration and the shift stays pending
+		#[weight = 10_000 + T::DbWeight::get().writes(2)]
+		pub fn edit_shift(origin, account: T::AccountId, index: u32, duration: T::Moment) -> dispatch::DispatchResult {
+			let who = ensure_signed(origin)?;
+			let mut shift = Self::pending_shift(&who, &account, index)?;
+			ensure!(duration <= shift.end.saturating_sub(shift.start), Error::<T>::ErrorInvalidShiftDuration);
+			let credit = shift.credit;
+			shift.credit = match shift.rate {
+				Some(_) if shift.duration.is_zero() => Zero::zero(),
+				Some(_) => shift.credit.saturated_into::<u128>()
+					.saturating_mul(duration.saturated_into::<u128>())
+					.checked_div(shift.duration.saturated_into::<u128>())
+					.unwrap_or(0)
+					.saturated_into(),
+				None => Zero::zero(),
+			};
+			PendingCredit::<T>::mutate(shift.org, |pending| *pending = pending.saturating_sub(credit).saturating_add(shift.credit));
+			Self::scale_segments(&mut shift.segments, credit, shift.credit);
+			shift.duration = duration;
+			Timesheets::<T>::insert(&account, index, shift);
+			Self::deposit_event(RawEvent::ShiftEdited(who, account, index, duration));
+			Ok(())
+		}
+
+		/// Reject a pending shift with a reason code, its credit is never paid
+		#[weight = 10_000 + T::DbWeight::get().writes(3)]
+		pub fn reject_shift(origin, account: T::AccountId, index: u32, reason: u8) -> dispatch::DispatchResult {
+			let who = ensure_signed(origin)?;
+			let mut shift = Self::pending_shift(&who, &account, index)?;
+			Disputes::<T>::remove(&account, index);
+			PendingCredit::<T>::mutate(shift.org, |pending| *pending = pending.saturating_sub(shift.credit));
+			shift.status = ShiftStatus::Rejected(reason);
+			Timesheets::<T>::insert(&account, index, shift);
+			Self::deposit_event(RawEvent::ShiftRejected(who, account, index, reason));
+			Ok(())
+		}
+
+		/// Request a correction of the enter and exit times of a recorded shift, or of the open
+		/// shift when `index` is the index of the next shift. The justification is kept off chain.
+		/// A shift can be corrected again once its last correction has been reviewed
+		#[weight = 10_000 + T::DbWeight::get().reads_writes(4, 2)]
+		pub fn request_correction(origin, index: u32, start: T::Moment, end: T::Moment, justification: T::Hash) -> dispatch::DispatchResult {
+			let who = ensure_signed(origin)?;
+			ensure!(start < end && end <= <timestamp::Module<T>>::get(), Error::<T>::ErrorInvalidCorrection);
+			let original = Self::correctable_shift(&who, index)?.1;
+			let number = Self::correction_count(&who, index);
+			if let Some(last) = number.checked_sub(1).and_then(|last| Self::corrections(&who, (index, last))) {
+				ensure!(last.status != CorrectionStatus::Pending, Error::<T>::ErrorCorrectionExists);
+			}
+			CorrectionCount::<T>::insert(&who, index, number.saturating_add(1));
+			Corrections::<T>::insert(&who, (index, number), CorrectionRequest {
+				start,
+				end,
+				justification,
+				original,
+				status: CorrectionStatus::Pending,
+			});
+			Self::deposit_event(RawEvent::CorrectionRequested(who, index, start, end, justification));
+			Ok(())
+		}
+
+		/// Approve a correction as an administrator of the organization of the shift. A recorded
+		/// shift is recalculated and the credit of the account adjusted, an open shift is closed
+		/// at the corrected times
+		#[weight = 10_000 + T::DbWeight::get().writes(6)]
+		pub fn approve_correction(origin, account: T::AccountId, index: u32) -> dispatch::DispatchResult {
+			let who = ensure_signed(origin)?;
+			let (number, mut correction) = Self::pending_correction(&who, &account, index)?;
+			let (org, original) = Self::correctable_shift(&account, index)?;
+			match Self::timesheets(&account, index) {
+				Some(shift) => Self::correct_shift(&account, index, shift, correction.start, correction.end),
+				None => {
+					Self::close_shift(org, &account, correction.start, correction.end, false, None);
+					Self::deposit_event(RawEvent::AccountExited(org, account.clone()));
 				},
-				_ => {
-					Err(Error::<T>::FailedToExit)?
-				}
 			}
+			correction.original = original;
+			correction.status = CorrectionStatus::Approved;
+			Corrections::<T>::insert(&account, (index, number), correction);
+			Self::deposit_event(RawEvent::CorrectionApproved(who, account, index));
+			Ok(())
+		}
+
+		/// Deny a correction as an administrator of the organization of the shift
+		#[weight = 10_000 + T::DbWeight::get().writes(1)]
+		pub fn deny_correction(origin, account: T::AccountId, index: u32) -> dispatch::DispatchResult {
+			let who = ensure_signed(origin)?;
+			let (number, mut correction) = Self::pending_correction(&who, &account, index)?;
+			correction.status = CorrectionStatus::Denied;
+			Corrections::<T>::insert(&account, (index, number), correction);
+			Self::deposit_event(RawEvent::CorrectionDenied(who, account, index));
+			Ok(())
 		}
 	}
 }
 
 impl<T: Trait> Module<T> {
 
-	pub fn account_id() -> T::AccountId {
-		PALLET_ID.into_account()
+	/// Let the administrators of an organization manage all of its roles
+	fn set_up_roles(organization: &Organization<T::Hash>) -> DispatchResult {
+		<access::Module<T>>::set_admin_for_role(organization.admin_role, organization.admin_role)?;
+		<access::Module<T>>::set_admin_for_role(organization.registrar_role, organization.admin_role)?;
+		<access::Module<T>>::set_admin_for_role(organization.approver_role, organization.admin_role)?;
+		<access::Module<T>>::set_admin_for_role(organization.supervisor_role, organization.admin_role)?;
+		// Administrators are registrars, who are supervisors
+		<access::Module<T>>::set_parent_for_role(organization.registrar_role, Some(organization.admin_role))?;
+		<access::Module<T>>::set_parent_for_role(organization.supervisor_role, Some(organization.registrar_role))?;
+		Ok(())
+	}
+
+	/// The pot of an organization, a sub-account of the pallet account
+	pub fn account_id(org: OrgId) -> T::AccountId {
+		PALLET_ID.into_sub_account(org)
 	}
 
-	pub fn check_if_registered(account: &T::AccountId) -> DispatchResult {
-		ensure!(Rates::<T>::contains_key(account), "account not registered");
+	pub fn check_if_registered(org: OrgId, account: &T::AccountId) -> DispatchResult {
+		ensure!(Rates::<T>::contains_key(org, account), "account not registered");
 		Ok(())
 	}
 
-	pub fn calculate_credit(time: T::Moment, rate: BalanceOf<T>) -> BalanceOf<T> {
-		let t : u64 = TryInto::<u64>::try_into(time).unwrap_or(0);
-		let r : u64 = TryInto::<u64>::try_into(rate).unwrap_or(0);
-		let credit = (t * r) / (60 * 60 * 1000);
-		TryInto::<BalanceOf<T>>::try_into(credit).unwrap_or(0.into())
+	/// Whether the account is an administrator of the organization
+	pub fn is_admin(org: OrgId, account: &T::AccountId) -> bool {
+		match Self::organizations(org) {
+			Some(organization) => <access::Module<T>>::has_role(organization.admin_role, account.clone()),
+			None => false,
+		}
+	}
+
+	pub fn ensure_admin(org: OrgId, account: &T::AccountId) -> DispatchResult {
+		ensure!(Organizations::<T>::contains_key(org), Error::<T>::ErrorOrganizationNotFound);
+		ensure!(Self::is_admin(org, account), Error::<T>::ErrorAdminRoleRequired);
+		Ok(())
+	}
+
+	/// Check the account is a supervisor of the organization and the worker is assigned to it
+	fn ensure_supervisor(org: OrgId, supervisor: &T::AccountId, worker: &T::AccountId) -> DispatchResult {
+		let organization = Self::organizations(org).ok_or(Error::<T>::ErrorOrganizationNotFound)?;
+		ensure!(<access::Module<T>>::has_role(organization.supervisor_role, supervisor.clone()),
+			Error::<T>::ErrorSupervisorRoleRequired);
+		ensure!(Self::supervised_workers((org, supervisor.clone()), worker), Error::<T>::ErrorWorkerNotAssigned);
+		Ok(())
+	}
+
+	fn is_expired(proposal: &RateProposal<T::AccountId, BalanceOf<T>, T::BlockNumber>) -> bool {
+		<frame_system::Module<T>>::block_number() > proposal.expires
+	}
+
+	/// The rate proposal has to be accepted or rejected by the account holder if an administrator
+	/// proposed it, or by another administrator if the account holder proposed it
+	fn ensure_counterparty(
+		org: OrgId,
+		who: &T::AccountId,
+		account: &T::AccountId,
+		proposal: &RateProposal<T::AccountId, BalanceOf<T>, T::BlockNumber>,
+	) -> DispatchResult {
+		if proposal.proposer == *account {
+			ensure!(who != account && Self::is_admin(org, who), Error::<T>::ErrorAdminRoleRequired);
+		} else {
+			ensure!(who == account, Error::<T>::ErrorAccountHolderRequired);
+		}
+		Ok(())
+	}
+
+	/// Set the rate of an account. Salary owed under the previous rate is credited first and
+	/// salaried accounts are kept in the payroll queue so they are paid every run
+	fn set_rate(org: OrgId, account: &T::AccountId, rate: Option<RateSpec<BalanceOf<T>>>) {
+		Self::accrue_salary(org, account);
+		Rates::<T>::mutate_exists(org, account, |r| *r = rate);
+		match rate {
+			Some(RateSpec::Salaried(_)) => {
+				if !SalaryAccruedAt::<T>::contains_key(org, account) {
+					SalaryAccruedAt::<T>::insert(org, account, <frame_system::Module<T>>::block_number());
+				}
+				Self::enqueue_payee(org, account);
+			},
+			_ => SalaryAccruedAt::<T>::remove(org, account),
+		}
+	}
+
+	/// Credit a salaried account for the blocks since its salary was last credited, prorated
+	/// over the payroll period
+	fn accrue_salary(org: OrgId, account: &T::AccountId) {
+		let salary = match Self::rates(org, account) {
+			Some(RateSpec::Salaried(salary)) => salary,
+			_ => return,
+		};
+		let now = <frame_system::Module<T>>::block_number();
+		let since = Self::salary_accrued_at(org, account).unwrap_or(now);
+		let period = T::PayrollPeriod::get().saturated_into::<u128>();
+		let elapsed = now.saturating_sub(since).saturated_into::<u128>();
+		SalaryAccruedAt::<T>::insert(org, account, now);
+		if period == 0 || elapsed == 0 {
+			return;
+		}
+		let amount: BalanceOf<T> = salary.saturated_into::<u128>()
+			.saturating_mul(elapsed)
+			.checked_div(period)
+			.unwrap_or(0)
+			.saturated_into();
+		if !amount.is_zero() {
+			Self::add_credit(org, account, amount);
+			Self::deposit_event(RawEvent::SalaryAccrued(org, account.clone(), amount));
+		}
+	}
+
+	/// Enter the account for an organization at `start`, against a project if given. An account
+	/// can only be entered for one organization at a time
+	fn do_enter(org: OrgId, who: T::AccountId, project: Option<ProjectId>, start: T::Moment) -> DispatchResult {
+		// Already entered
+		ensure!(Self::entered(&who).is_none(), Error::<T>::FailedToEnter);
+		Entered::<T>::insert(&who, (org, start));
+		ActiveProject::<T>::mutate_exists(&who, |p| *p = project);
+		Self::deposit_event(RawEvent::AccountEntered(org, who));
+		Ok(())
+	}
+
+	/// Store the random seed of the challenge codes for block `n` and drop the seed of the block
+	/// which is now too old to be accepted
+	fn rotate_challenge_seed(n: T::BlockNumber) -> Weight {
+		let max_age = T::MaxChallengeAge::get();
+		if max_age.is_zero() {
+			return 0;
+		}
+		ChallengeSeeds::<T>::insert(n, T::Randomness::random(&b"timekeeper-challenge"[..]));
+		if n > max_age {
+			ChallengeSeeds::<T>::remove(n - max_age - One::one());
+		}
+		T::DbWeight::get().writes(2)
+	}
+
+	/// The challenge code shown on the kiosk of a site of an organization at a block, while the
+	/// seed of the block is kept
+	pub fn challenge_code(org: OrgId, site: SiteId, block: T::BlockNumber) -> Option<u32> {
+		Self::challenge_seed(block).map(|seed| {
+			let hash = T::Hashing::hash_of(&(seed, org, site, block));
+			u32::decode(&mut hash.as_ref()).unwrap_or_default() % CHALLENGE_CODE_MODULUS
+		})
+	}
+
+	/// Check the account gave a recent challenge code of a site it is allowed at, if the
+	/// organization requires one. A failed challenge is counted even though the call fails
+	fn check_challenge(org: OrgId, who: &T::AccountId, challenge: Option<Challenge<T::BlockNumber>>) -> DispatchResult {
+		let age = match Self::challenge_age(org) {
+			Some(age) => age,
+			None => return Ok(()),
+		};
+		let now = <frame_system::Module<T>>::block_number();
+		let valid = challenge.map_or(false, |c| {
+			c.block <= now
+				&& now - c.block <= age
+				&& Self::site_workers((org, c.site), who)
+				&& Self::challenge_code(org, c.site, c.block) == Some(c.code)
+		});
+		if !valid {
+			FailedChallenges::<T>::mutate(org, who, |failed| *failed = failed.saturating_add(1));
+			Self::deposit_event(RawEvent::ChallengeFailed(org, who.clone()));
+			Err(Error::<T>::ErrorChallengeFailed)?
+		}
+		Ok(())
+	}
+
+	/// Exit the account, crediting it for the shift since it entered. `exited_by` is the
+	/// supervisor exiting it, if any
+	fn do_exit(who: T::AccountId, exited_by: Option<T::AccountId>) -> DispatchResult {
+		match Self::entered(&who) {
+			Some((org, timestamp)) => {
+				let now = <timestamp::Module<T>>::get();
+				Self::close_shift(org, &who, timestamp, now, false, exited_by);
+				// Emit an event.
+				Self::deposit_event(RawEvent::AccountExited(org, who));
+				// Return a successful DispatchResult
+				Ok(())
+			},
+			_ => {
+				Err(Error::<T>::FailedToExit)?
+			}
+		}
+	}
+
+	/// Check a punch is signed by the worker and an active device at a site the worker is
+	/// allowed to punch at, and isn't a replay. Returns the device
+	fn check_punch_signatures(
+		worker: &T::AccountId,
+		device: &T::AccountId,
+		nonce: u32,
+		payload: &[u8],
+		worker_signature: &T::Signature,
+		device_signature: &T::Signature,
+	) -> Result<Device, Error<T>> {
+		let d = Self::devices(device).ok_or(Error::<T>::ErrorDeviceNotFound)?;
+		ensure!(d.active, Error::<T>::ErrorDeviceInactive);
+		ensure!(Self::site_workers((d.org, d.site), worker), Error::<T>::ErrorNotSiteWorker);
+		ensure!(nonce == Self::punch_nonce(worker), Error::<T>::ErrorInvalidPunchNonce);
+		ensure!(worker_signature.verify(payload, worker) && device_signature.verify(payload, device),
+			Error::<T>::ErrorInvalidPunchSignature);
+		Ok(d)
+	}
+
+	/// Apply an offline punch at the time it was recorded. The punch has to be recent enough
+	/// and follow the last punch of the worker. Its nonce is used up once the signatures are
+	/// checked, even if it can't be applied, so the punches after it can still be
+	fn apply_offline_punch(
+		punch: &OfflinePunch<T::AccountId, T::Moment>,
+		worker_signature: &T::Signature,
+		device_signature: &T::Signature,
+	) -> DispatchResult {
+		let worker = &punch.worker;
+		let device = Self::check_punch_signatures(worker, &punch.device, punch.nonce, &punch.encode(), worker_signature, device_signature)?;
+		PunchNonces::<T>::insert(worker, punch.nonce.saturating_add(1));
+		let now = <timestamp::Module<T>>::get();
+		ensure!(punch.time <= now, Error::<T>::ErrorPunchInFuture);
+		ensure!(now - punch.time <= T::MaxBackdating::get(), Error::<T>::ErrorPunchTooOld);
+		match punch.kind {
+			PunchKind::Enter => {
+				Self::check_if_registered(device.org, worker)?;
+				let last_exit = Self::shift_count(worker).checked_sub(1)
+					.and_then(|index| Self::timesheets(worker, index))
+					.map(|shift| shift.end);
+				ensure!(last_exit.map_or(true, |end| punch.time >= end), Error::<T>::ErrorPunchOutOfOrder);
+				Self::do_enter(device.org, worker.clone(), None, punch.time)?;
+			},
+			PunchKind::Exit => {
+				let (org, start) = Self::entered(worker).ok_or(Error::<T>::FailedToExit)?;
+				ensure!(org == device.org, Error::<T>::FailedToExit);
+				ensure!(punch.time >= start, Error::<T>::ErrorPunchOutOfOrder);
+				// Shifts longer than the maximum are credited up to it, as if auto exited
+				let max = Self::max_shift_duration(org, worker);
+				if punch.time.saturating_sub(start) > max {
+					Self::close_shift(org, worker, start, start.saturating_add(max), true, None);
+					Self::deposit_event(RawEvent::AutoExited(org, worker.clone(), max));
+				} else {
+					Self::close_shift(org, worker, start, punch.time, false, None);
+					Self::deposit_event(RawEvent::AccountExited(org, worker.clone()));
+				}
+			},
+		}
+		Self::deposit_event(RawEvent::Punched(punch.device.clone(), worker.clone(), punch.kind));
+		Ok(())
+	}
+
+	/// The maximum shift duration for an account, the override if set otherwise the default
+	pub fn max_shift_duration(org: OrgId, account: &T::AccountId) -> T::Moment {
+		Self::max_shift_durations(org, account).unwrap_or_else(T::MaxShiftDuration::get)
+	}
+
+	/// Exit the account, credit it for the time between `start` and `end` outside unpaid breaks
+	/// and record the shift in its timesheet, with the supervisors who entered and exited it
+	fn close_shift(
+		org: OrgId,
+		account: &T::AccountId,
+		start: T::Moment,
+		end: T::Moment,
+		auto_exited: bool,
+		exited_by: Option<T::AccountId>,
+	) {
+		Entered::<T>::remove(account);
+		let entered_by = EnteredBy::<T>::take(account);
+		let project = ActiveProject::<T>::take(account);
+		let breaks = Self::close_breaks(account, start, end);
+		let rate = Self::rates(org, account);
+		let mut worked = Self::worked_times(org, account);
+		let mut remainder = Self::credit_remainders(org, account);
+		let (duration, credit, segments) = Self::price_shift(org, start, end, &breaks, rate, &mut worked, &mut remainder);
+		if rate.is_some() {
+			WorkedTimes::<T>::insert(org, account, worked);
+			CreditRemainders::<T>::insert(org, account, remainder);
+		}
+
+		let index = Self::shift_count(account);
+		let shift = ShiftRecord {
+			org,
+			start,
+			end,
+			duration,
+			rate,
+			credit,
+			auto_exited,
+			segments,
+			breaks,
+			project,
+			status: ShiftStatus::Pending,
+			entered_by,
+			exited_by,
+		};
+		ShiftCount::<T>::insert(account, index + 1);
+		PendingCredit::<T>::mutate(org, |pending| *pending = pending.saturating_add(credit));
+		Self::deposit_event(RawEvent::ShiftRecorded(account.clone(), index));
+		// Shifts recorded by a supervisor wait for review even without an approval window, so the
+		// worker can dispute them
+		if Self::approval_window(org).is_none() && (shift.entered_by.is_some() || shift.exited_by.is_some()) {
+			Timesheets::<T>::insert(account, index, shift);
+			return;
+		}
+		Self::queue_approval(account, index, shift);
+	}
+
+	/// Queue a pending shift to be approved automatically at the end of the approval window of
+	/// its organization, or approve it if the organization has none
+	fn queue_approval(account: &T::AccountId, index: u32, shift: ShiftRecord<T::AccountId, T::Moment, BalanceOf<T>>) {
+		match Self::approval_window(shift.org) {
+			Some(window) => {
+				let at = <frame_system::Module<T>>::block_number().saturating_add(window);
+				Timesheets::<T>::insert(account, index, shift);
+				PendingApprovals::<T>::mutate(at, |pending| pending.push((account.clone(), index)));
+				Self::deposit_event(RawEvent::ShiftPending(account.clone(), index, at));
+			},
+			None => Self::approve(account, index, shift),
+		}
+	}
+
+	/// The pending shift of an account, which `who` has to be an approver of the organization of
+	/// the shift to review. Approvers can't review their own shifts
+	fn pending_shift(
+		who: &T::AccountId,
+		account: &T::AccountId,
+		index: u32,
+	) -> Result<ShiftRecord<T::AccountId, T::Moment, BalanceOf<T>>, dispatch::DispatchError> {
+		let shift = Self::timesheets(account, index).ok_or(Error::<T>::ErrorShiftNotFound)?;
+		let organization = Self::organizations(shift.org).ok_or(Error::<T>::ErrorOrganizationNotFound)?;
+		ensure!(who != account && <access::Module<T>>::has_role(organization.approver_role, who.clone()),
+			Error::<T>::ErrorApproverRoleRequired);
+		ensure!(shift.status == ShiftStatus::Pending, Error::<T>::ErrorShiftNotPending);
+		Ok(shift)
+	}
+
+	/// Scale the credit of the segments of a shift from `from` to `to`, rounding down and leaving
+	/// the remainder on the last segment so they still add up to the credit of the shift
+	fn scale_segments(segments: &mut Vec<ShiftSegment<T::Moment, BalanceOf<T>>>, from: BalanceOf<T>, to: BalanceOf<T>) {
+		let from = from.saturated_into::<u128>();
+		let mut left = to;
+		let last = segments.len().saturating_sub(1);
+		for (i, segment) in segments.iter_mut().enumerate() {
+			segment.credit = if i == last {
+				left
+			} else {
+				segment.credit.saturated_into::<u128>()
+					.saturating_mul(to.saturated_into::<u128>())
+					.checked_div(from)
+					.unwrap_or(0)
+					.saturated_into::<BalanceOf<T>>()
+					.min(left)
+			};
+			left = left.saturating_sub(segment.credit);
+		}
+	}
+
+	/// Approve a shift, crediting the account and billing the project it was worked on
+	fn approve(account: &T::AccountId, index: u32, mut shift: ShiftRecord<T::AccountId, T::Moment, BalanceOf<T>>) {
+		PendingCredit::<T>::mutate(shift.org, |pending| *pending = pending.saturating_sub(shift.credit));
+		Self::add_credit(shift.org, account, shift.credit);
+		if let Some(project) = shift.project {
+			Self::bill_project(project, shift.duration, shift.credit);
+		}
+		shift.status = ShiftStatus::Approved;
+		Timesheets::<T>::insert(account, index, shift);
+	}
+
+	/// The organization of a shift which can be corrected and its enter and exit times, either a
+	/// recorded shift which hasn't been rejected or the open shift of the account
+	fn correctable_shift(account: &T::AccountId, index: u32) -> Result<(OrgId, (T::Moment, Option<T::Moment>)), dispatch::DispatchError> {
+		if let Some(shift) = Self::timesheets(account, index) {
+			ensure!(!matches!(shift.status, ShiftStatus::Rejected(_)), Error::<T>::ErrorShiftRejected);
+			return Ok((shift.org, (shift.start, Some(shift.end))));
+		}
+		match Self::entered(account) {
+			Some((org, start)) if index == Self::shift_count(account) => Ok((org, (start, None))),
+			_ => Err(Error::<T>::ErrorShiftNotFound.into()),
+		}
+	}
+
+	/// The pending correction of a shift of an account and its number, which `who` has to be an
+	/// administrator of the organization of the shift to review. Administrators can't review
+	/// their own corrections
+	fn pending_correction(
+		who: &T::AccountId,
+		account: &T::AccountId,
+		index: u32,
+	) -> Result<(u32, CorrectionRequest<T::Moment, T::Hash>), dispatch::DispatchError> {
+		let number = Self::correction_count(account, index).checked_sub(1).ok_or(Error::<T>::ErrorCorrectionNotFound)?;
+		let correction = Self::corrections(account, (index, number)).ok_or(Error::<T>::ErrorCorrectionNotFound)?;
+		ensure!(correction.status == CorrectionStatus::Pending, Error::<T>::ErrorCorrectionNotPending);
+		let (org, _) = Self::correctable_shift(account, index)?;
+		Self::ensure_admin(org, who)?;
+		ensure!(who != account, Error::<T>::ErrorAdminRoleRequired);
+		Ok((number, correction))
+	}
+
+	/// Recalculate a recorded shift between the corrected `start` and `end`, keeping the breaks
+	/// taken within them. Overtime is counted from the start of the corrected shift. If the shift
+	/// was approved the difference is added to or taken from the credit of the account and the
+	/// project it was worked on, credit already withdrawn isn't taken back
+	fn correct_shift(
+		account: &T::AccountId,
+		index: u32,
+		mut shift: ShiftRecord<T::AccountId, T::Moment, BalanceOf<T>>,
+		start: T::Moment,
+		end: T::Moment,
+	) {
+		let breaks: Vec<BreakRecord<T::Moment>> = shift.breaks.iter()
+			.filter(|b| b.end > start && b.start < end)
+			.map(|b| BreakRecord { start: b.start.max(start), end: b.end.min(end), paid: b.paid })
+			.collect();
+		let (duration, credit, segments) = Self::price_shift(
+			shift.org,
+			start,
+			end,
+			&breaks,
+			shift.rate,
+			&mut WorkedTime::default(),
+			&mut FixedU128::from_inner(0),
+		);
+		if shift.status == ShiftStatus::Approved {
+			if credit > shift.credit {
+				Self::add_credit(shift.org, account, credit - shift.credit);
+			} else {
+				Self::remove_credit(shift.org, account, shift.credit - credit);
+			}
+			if let Some(project) = shift.project {
+				Projects::<T>::mutate(project, |p| {
+					if let Some(p) = p {
+						p.time = p.time.saturating_sub(shift.duration);
+						p.credit = p.credit.saturating_sub(shift.credit);
+					}
+				});
+				Self::bill_project(project, duration, credit);
+			}
+		} else if shift.status == ShiftStatus::Pending {
+			PendingCredit::<T>::mutate(shift.org, |pending| *pending = pending.saturating_sub(shift.credit).saturating_add(credit));
+		}
+		shift.start = start;
+		shift.end = end;
+		shift.duration = duration;
+		shift.credit = credit;
+		shift.segments = segments;
+		shift.breaks = breaks;
+		Timesheets::<T>::insert(account, index, shift);
+	}
+
+	/// Approve the shifts nobody reviewed before block `n`
+	fn approve_overdue_shifts(n: T::BlockNumber) -> Weight {
+		let pending = PendingApprovals::<T>::take(n);
+		let mut approved: Weight = 0;
+		for (account, index) in pending.iter() {
+			if Disputes::<T>::contains_key(account, *index) {
+				continue;
+			}
+			if let Some(shift) = Self::timesheets(account, *index).filter(|s| s.status == ShiftStatus::Pending) {
+				Self::approve(account, *index, shift);
+				Self::deposit_event(RawEvent::ShiftAutoApproved(account.clone(), *index));
+				approved += 1;
+			}
+		}
+		T::DbWeight::get().reads_writes(1 + 2 * pending.len() as Weight, 1 + approved * 5)
+	}
+
+	/// Add the time and credit of a shift to a project, warning when it crosses 80% and 100% of
+	/// its time or credit budget
+	fn bill_project(project: ProjectId, time: T::Moment, credit: BalanceOf<T>) {
+		let mut p = match Self::projects(project) {
+			Some(p) => p,
+			None => return,
+		};
+		let crossed = |percent: u128, budget: u128, before: u128, after: u128| {
+			let threshold = budget.saturating_mul(percent);
+			before.saturating_mul(100) < threshold && after.saturating_mul(100) >= threshold
+		};
+		let mut warning = false;
+		let mut exhausted = false;
+		let time_after = p.time.saturating_add(time);
+		if let Some(budget) = p.time_budget {
+			let (budget, before, after) = (budget.saturated_into::<u128>(), p.time.saturated_into::<u128>(), time_after.saturated_into::<u128>());
+			warning |= crossed(80, budget, before, after);
+			exhausted |= crossed(100, budget, before, after);
+		}
+		let credit_after = p.credit.saturating_add(credit);
+		if let Some(budget) = p.credit_budget {
+			let (budget, before, after) = (budget.saturated_into::<u128>(), p.credit.saturated_into::<u128>(), credit_after.saturated_into::<u128>());
+			warning |= crossed(80, budget, before, after);
+			exhausted |= crossed(100, budget, before, after);
+		}
+		p.time = time_after;
+		p.credit = credit_after;
+		Projects::<T>::insert(project, p);
+		if warning {
+			Self::deposit_event(RawEvent::BudgetWarning(project));
+		}
+		if exhausted {
+			Self::deposit_event(RawEvent::BudgetExhausted(project));
+		}
+	}
+
+	/// The time credited for a shift between `start` and `end` outside unpaid breaks, the credit
+	/// earned at `rate` and the parts of the shift paid at different multipliers. `worked` is the
+	/// time already worked in the day and week and `remainder` the fraction of a unit of credit
+	/// carried over, both are updated with the shift
+	fn price_shift(
+		org: OrgId,
+		start: T::Moment,
+		end: T::Moment,
+		breaks: &[BreakRecord<T::Moment>],
+		rate: Option<RateSpec<BalanceOf<T>>>,
+		worked: &mut WorkedTime<T::Moment>,
+		remainder: &mut FixedU128,
+	) -> (T::Moment, BalanceOf<T>, Vec<ShiftSegment<T::Moment, BalanceOf<T>>>) {
+		// The time worked between unpaid breaks
+		let mut intervals = Vec::new();
+		let mut cursor = start;
+		for b in breaks.iter().filter(|b| !b.paid) {
+			if b.start > cursor {
+				intervals.push((cursor, b.start));
+			}
+			cursor = cursor.max(b.end);
+		}
+		if end > cursor {
+			intervals.push((cursor, end));
+		}
+		let duration = intervals.iter()
+			.fold(Zero::zero(), |total: T::Moment, (from, to)| total.saturating_add(to.saturating_sub(*from)));
+
+		let mut credit: BalanceOf<T> = Zero::zero();
+		let mut segments = Vec::new();
+		if let Some(r) = rate {
+			let policy = Self::pay_policy(org).unwrap_or_default();
+			for (from, to) in intervals {
+				for (segment_start, segment_end, multiplier) in Self::split_shift(&policy, worked, from, to) {
+					let time = segment_end.saturating_sub(segment_start)
+						.saturating_mul(multiplier as u128) / BASE_MULTIPLIER as u128;
+					let (c, rest) = Self::calculate_credit_with_remainder(time.saturated_into(), r, *remainder);
+					*remainder = rest;
+					credit = credit.saturating_add(c);
+					segments.push(ShiftSegment {
+						start: segment_start.saturated_into(),
+						end: segment_end.saturated_into(),
+						multiplier,
+						credit: c,
+					});
+				}
+			}
+		}
+		(duration, credit, segments)
+	}
+
+	/// End the break in progress and take the breaks of the shift between `start` and `end`.
+	/// Breaks after the end of the shift are dropped and, if no break was taken in a long shift,
+	/// an unpaid break is deducted from its end
+	fn close_breaks(account: &T::AccountId, start: T::Moment, end: T::Moment) -> Vec<BreakRecord<T::Moment>> {
+		let mut breaks = Breaks::<T>::take(account);
+		if let Some((break_start, paid)) = OnBreak::<T>::take(account) {
+			breaks.push(BreakRecord { start: break_start, end, paid });
+		}
+		let mut breaks: Vec<BreakRecord<T::Moment>> = breaks.into_iter()
+			.filter(|b| b.start < end)
+			.map(|b| BreakRecord { end: b.end.min(end), ..b })
+			.collect();
+		let after = T::AutoBreakAfter::get();
+		let duration = end.saturating_sub(start);
+		if breaks.is_empty() && !after.is_zero() && duration > after {
+			let deducted = T::AutoBreakDuration::get().min(duration);
+			if !deducted.is_zero() {
+				breaks.push(BreakRecord { start: end - deducted, end, paid: false });
+			}
+		}
+		breaks
+	}
+
+	/// Split the time between `start` and `end` into segments paid at the same multiplier of the
+	/// pay policy, as (start, end, multiplier). The time is added to `worked`, the time worked in
+	/// the day and week
+	fn split_shift(policy: &PayPolicy<T::Moment>, worked: &mut WorkedTime<T::Moment>, start: T::Moment, end: T::Moment) -> Vec<(u128, u128, u32)> {
+		let daily_threshold = policy.daily_threshold.map(|t| t.saturated_into::<u128>());
+		let weekly_threshold = policy.weekly_threshold.map(|t| t.saturated_into::<u128>());
+		let night = policy.night.map(|(s, e)| (s.saturated_into::<u128>(), e.saturated_into::<u128>()));
+		let (mut day, mut week) = (worked.day as u128, worked.week as u128);
+		let (mut daily, mut weekly) = (worked.daily.saturated_into::<u128>(), worked.weekly.saturated_into::<u128>());
+
+		let end = end.saturated_into::<u128>();
+		let mut cursor = start.saturated_into::<u128>();
+		let mut segments: Vec<(u128, u128, u32)> = Vec::new();
+		while cursor < end {
+			// The epoch was a Thursday, so day 4 is the first Monday
+			let today = cursor / MILLISECS_PER_DAY;
+			let weekday = (today + 3) % 7;
+			if today != day {
+				day = today;
+				daily = 0;
+			}
+			if (today + 3) / 7 != week {
+				week = (today + 3) / 7;
+				weekly = 0;
+			}
+			let time_of_day = cursor % MILLISECS_PER_DAY;
+			let mut next = end.min((today + 1) * MILLISECS_PER_DAY);
+			let mut multiplier = BASE_MULTIPLIER;
+			for (threshold, worked) in [(daily_threshold, daily), (weekly_threshold, weekly)].iter() {
+				if let Some(threshold) = threshold {
+					if worked >= threshold {
+						multiplier = multiplier.max(policy.overtime_multiplier);
+					} else {
+						next = next.min(cursor + (threshold - worked));
+					}
+				}
+			}
+			if let Some((night_start, night_end)) = night {
+				let is_night = if night_start <= night_end {
+					night_start <= time_of_day && time_of_day < night_end
+				} else {
+					time_of_day >= night_start || time_of_day < night_end
+				};
+				if is_night {
+					multiplier = multiplier.max(policy.night_multiplier);
+				}
+				for edge in [night_start, night_end].iter() {
+					if *edge > time_of_day {
+						next = next.min(today * MILLISECS_PER_DAY + edge);
+					}
+				}
+			}
+			if weekday >= 5 {
+				multiplier = multiplier.max(policy.weekend_multiplier);
+			}
+
+			daily = daily.saturating_add(next - cursor);
+			weekly = weekly.saturating_add(next - cursor);
+			match segments.last_mut() {
+				Some(last) if last.2 == multiplier => last.1 = next,
+				_ => segments.push((cursor, next, multiplier)),
+			}
+			cursor = next;
+		}
+
+		*worked = WorkedTime {
+			day: day.saturated_into(),
+			daily: daily.saturated_into(),
+			week: week.saturated_into(),
+			weekly: weekly.saturated_into(),
+		};
+		segments
+	}
+
+	/// Transfer `amount` of the credit of an account from the pot of the organization, keeping
+	/// the remaining credit
+	fn do_withdraw(org: OrgId, who: T::AccountId, amount: BalanceOf<T>) -> DispatchResult {
+		ensure!(!amount.is_zero(), Error::<T>::FailedToWithdraw);
+		Creditors::<T>::try_mutate_exists(org, &who, |credit| -> DispatchResult {
+			let balance = (*credit).ok_or(Error::<T>::FailedCredit)?;
+			ensure!(balance >= amount, Error::<T>::FailedInsufficientCredit);
+			ensure!(Self::pot_balance(org) >= amount, Error::<T>::ErrorUnderfunded);
+			let remaining = balance - amount;
+
+			with_transaction_result(|| {
+				T::Currency::transfer(
+					&Self::account_id(org),
+					&who,
+					amount,
+					ExistenceRequirement::AllowDeath
+				)?;
+				Ok(())
+			})?;
+
+			*credit = if remaining.is_zero() { None } else { Some(remaining) };
+			TotalOutstanding::<T>::mutate(org, |total| *total = total.saturating_sub(amount));
+			Self::deposit_event(RawEvent::Withdrawn(org, who.clone(), amount, remaining));
+			Ok(())
+		})
+	}
+
+	/// Credit an account for work done for an organization, queueing it for the next payroll
+	pub fn add_credit(org: OrgId, account: &T::AccountId, amount: BalanceOf<T>) {
+		if amount.is_zero() {
+			return;
+		}
+		let solvent = Self::is_solvent(org);
+		Creditors::<T>::mutate_exists(org, account, |c| {
+			*c = Some(c.unwrap_or_else(Zero::zero).saturating_add(amount));
+		});
+		TotalOutstanding::<T>::mutate(org, |total| *total = total.saturating_add(amount));
+		Self::enqueue_payee(org, account);
+		if solvent && !Self::is_solvent(org) {
+			Self::deposit_event(RawEvent::Underfunded(org, Self::pot_balance(org), Self::total_outstanding(org)));
+		}
+	}
+
+	/// Take up to `amount` from the credit of an account with an organization
+	fn remove_credit(org: OrgId, account: &T::AccountId, amount: BalanceOf<T>) {
+		let mut removed: BalanceOf<T> = Zero::zero();
+		Creditors::<T>::mutate_exists(org, account, |c| {
+			if let Some(balance) = *c {
+				removed = amount.min(balance);
+				let remaining = balance - removed;
+				*c = if remaining.is_zero() { None } else { Some(remaining) };
+			}
+		});
+		TotalOutstanding::<T>::mutate(org, |total| *total = total.saturating_sub(removed));
+	}
+
+	/// The funds held by the pot of an organization to pay its creditors
+	pub fn pot_balance(org: OrgId) -> BalanceOf<T> {
+		T::Currency::free_balance(&Self::account_id(org))
+	}
+
+	/// Whether the pot of an organization holds enough to pay all the credit it owes
+	pub fn is_solvent(org: OrgId) -> bool {
+		Self::pot_balance(org) >= Self::total_outstanding(org)
+	}
+
+	/// The funds held by the pot of an organization above the credit it owes and the credit of
+	/// its pending shifts
+	pub fn surplus(org: OrgId) -> BalanceOf<T> {
+		Self::pot_balance(org)
+			.saturating_sub(Self::total_outstanding(org))
+			.saturating_sub(Self::pending_credit(org))
+	}
+
+	/// The credit owed by an organization above the funds held by its pot
+	pub fn shortfall(org: OrgId) -> BalanceOf<T> {
+		Self::total_outstanding(org).saturating_sub(Self::pot_balance(org))
+	}
+
+	/// Add the account at the end of the payroll queue of the organization, if it isn't already
+	/// queued
+	fn enqueue_payee(org: OrgId, account: &T::AccountId) {
+		if !PayrollQueued::<T>::contains_key(org, account) {
+			let tail = Self::payroll_queue_tail();
+			PayrollQueue::<T>::insert(tail, (org, account.clone()));
+			PayrollQueued::<T>::insert(org, account, tail);
+			PayrollQueueTail::put(tail.saturating_add(1));
+		}
+	}
+
+	/// Start a payroll run for every account queued so far, unless one is already in progress
+	fn start_payroll(n: T::BlockNumber) -> Weight {
+		if Self::payroll_end().is_some() {
+			return T::DbWeight::get().reads(1);
+		}
+		let tail = Self::payroll_queue_tail();
+		PayrollEnd::put(tail);
+		PayrollRun::mutate(|run| *run = run.wrapping_add(1));
+		Self::deposit_event(RawEvent::PayrollStarted(n, tail.saturating_sub(Self::payroll_queue_head())));
+		T::DbWeight::get().reads_writes(4, 2)
+	}
+
+	/// The fraction of their credit the creditors of an organization are paid by the payroll in
+	/// progress, if it is paying them pro rata
+	pub fn payroll_ratio(org: OrgId) -> Option<Perbill> {
+		match (Self::payroll_end(), Self::payroll_ratios(org)) {
+			(Some(_), Some((run, ratio))) if run == Self::payroll_run() => ratio,
+			_ => None,
+		}
+	}
+
+	/// Work out how much of their credit the creditors of an organization are paid by the payroll
+	/// in progress, once per run so the run only looks at the organizations it pays
+	fn settle_payroll_ratio(org: OrgId) -> Option<Perbill> {
+		let run = Self::payroll_run();
+		if let Some((settled, ratio)) = Self::payroll_ratios(org) {
+			if settled == run {
+				return ratio;
+			}
+		}
+		let outstanding = Self::total_outstanding(org);
+		let pot = Self::pot_balance(org);
+		let mut ratio = None;
+		if pot < outstanding {
+			Self::deposit_event(RawEvent::Underfunded(org, pot, outstanding));
+			if Self::settlement(org) == SettlementMode::ProRata {
+				let partial = Perbill::from_rational_approximation(pot, outstanding);
+				Self::deposit_event(RawEvent::PartialPayroll(org, partial));
+				ratio = Some(partial);
+			}
+		}
+		PayrollRatios::insert(org, (run, ratio));
+		ratio
+	}
+
+	/// Pay the next batch of accounts of the payroll run in progress
+	fn process_payroll() -> Weight {
+		let end = match Self::payroll_end() {
+			Some(end) => end,
+			None => return T::DbWeight::get().reads(1),
+		};
+		let mut head = Self::payroll_queue_head();
+		let mut processed: u32 = 0;
+		while head < end && processed < T::PayrollBatchSize::get() {
+			if let Some((org, account)) = PayrollQueue::<T>::take(head) {
+				PayrollQueued::<T>::remove(org, &account);
+				Self::pay_account(org, &account, Self::settle_payroll_ratio(org));
+			}
+			head += 1;
+			processed += 1;
+		}
+		PayrollQueueHead::put(head);
+		if head >= end {
+			PayrollEnd::kill();
+			Self::deposit_event(RawEvent::PayrollCompleted);
+		}
+		let processed = processed as Weight;
+		T::DbWeight::get().reads_writes(3 + processed * 10, 3 + processed * 7)
+	}
+
+	/// Transfer the credit of an account from the pot of the organization, or the fraction
+	/// `ratio` of it when the payroll is paying pro rata. Credit which isn't paid is kept and the
+	/// account queued again
+	fn pay_account(org: OrgId, account: &T::AccountId, ratio: Option<Perbill>) {
+		Self::accrue_salary(org, account);
+		if SalaryAccruedAt::<T>::contains_key(org, account) {
+			Self::enqueue_payee(org, account);
+		}
+		if let Some(credit) = Self::creditors(org, account) {
+			let amount = match ratio {
+				Some(ratio) => ratio.mul_floor(credit),
+				None => credit,
+			};
+			if amount.is_zero() {
+				Self::enqueue_payee(org, account);
+				return;
+			}
+			match T::Currency::transfer(&Self::account_id(org), account, amount, ExistenceRequirement::AllowDeath) {
+				Ok(()) => {
+					let remaining = credit - amount;
+					if remaining.is_zero() {
+						Creditors::<T>::remove(org, account);
+					} else {
+						Creditors::<T>::insert(org, account, remaining);
+						Self::enqueue_payee(org, account);
+					}
+					TotalOutstanding::<T>::mutate(org, |total| *total = total.saturating_sub(amount));
+					Self::deposit_event(RawEvent::AccountPaid(org, account.clone(), amount));
+				},
+				Err(_) => {
+					Self::enqueue_payee(org, account);
+					Self::deposit_event(RawEvent::PaymentFailed(org, account.clone(), amount));
+				}
+			}
+		}
+	}
+
+	/// Check the next batch of entered accounts, carrying on from where the last block stopped,
+	/// and exit those entered for longer than their maximum shift duration, crediting them up
+	/// to the maximum. Once every account has been checked the next block starts over
+	fn exit_overdue_accounts() -> Weight {
+		let now = <timestamp::Module<T>>::get();
+		let prefix = Entered::<T>::final_prefix();
+		let mut key = Self::exit_scan_key().unwrap_or_else(|| prefix.to_vec());
+		let mut checked: u32 = 0;
+		let mut overdue: Vec<(OrgId, T::AccountId, T::Moment, T::Moment)> = Vec::new();
+		while checked < T::AutoExitBatchSize::get() {
+			match sp_io::storage::next_key(&key).filter(|next| next.starts_with(&prefix)) {
+				Some(next) => key = next,
+				// Start over straight away if the last block checked the last account
+				None if checked == 0 && key != prefix => {
+					key = prefix.to_vec();
+					continue;
+				},
+				None => {
+					key = prefix.to_vec();
+					break;
+				},
+			}
+			checked += 1;
+			let account = match T::AccountId::decode(&mut Blake2_128Concat::reverse(&key[prefix.len()..])) {
+				Ok(account) => account,
+				Err(_) => continue,
+			};
+			if let Some((org, start)) = Self::entered(&account) {
+				let max = Self::max_shift_duration(org, &account);
+				if now.saturating_sub(start) > max {
+					overdue.push((org, account, start, max));
+				}
+			}
+		}
+		ExitScanKey::put(key);
+
+		let reads = 1 + checked as Weight * 3;
+		let exited = overdue.len() as Weight;
+		for (org, account, start, max) in overdue {
+			Self::close_shift(org, &account, start, start.saturating_add(max), true, None);
+			Self::deposit_event(RawEvent::AutoExited(org, account, max));
+		}
+
+		T::DbWeight::get().reads_writes(reads + exited * 2, 1 + exited * 4)
+	}
+
+	/// Bring the storage of the first release up to date
+	fn migrate_to_v2() -> Weight {
+		let mut weight = T::DbWeight::get().reads_writes(1, 1);
+		weight = weight.saturating_add(Self::migrate_rate_units());
+		weight = weight.saturating_add(Self::migrate_to_organizations());
+		StorageVersion::put(Releases::V2);
+		weight
+	}
+
+	/// Quote the rates of the first release, plain balances paid per hour, as hourly rates. The
+	/// accounts registered then belong to the organization `LEGACY_ORG`
+	fn migrate_rate_units() -> Weight {
+		let rates: Vec<(T::AccountId, BalanceOf<T>)> =
+			StorageKeyIterator::<T::AccountId, BalanceOf<T>, Blake2_128Concat>::new(b"Timekeeper", b"Rates")
+				.drain()
+				.collect();
+		let count = rates.len() as Weight;
+		for (account, rate) in rates {
+			Rates::<T>::insert(LEGACY_ORG, account, RateSpec::Hourly(rate));
+		}
+		T::DbWeight::get().reads_writes(count, count * 2)
+	}
+
+	/// Move the accounts, credit and pot of the first release to the organization `LEGACY_ORG`,
+	/// keeping its registrars and letting the timekeeper administrators administer it
+	fn migrate_to_organizations() -> Weight {
+		let registrar_role = take_storage_value::<T::Hash>(b"Timekeeper", b"RegistrarRole", &[])
+			.unwrap_or_else(|| T::Hashing::hash_of(&("timekeeper-registrar", LEGACY_ORG)));
+		let organization = Organization {
+			admin_role: T::Hashing::hash_of(&("timekeeper-administrator", LEGACY_ORG)),
+			registrar_role,
+			approver_role: T::Hashing::hash_of(&("timekeeper-approver", LEGACY_ORG)),
+			supervisor_role: T::Hashing::hash_of(&("timekeeper-supervisor", LEGACY_ORG)),
+		};
+		// The registrar role was administered by the timekeeper administrators, who now
+		// administer the organization through its administrator role
+		<access::AdminRoles<T>>::remove(organization.registrar_role);
+		let _ = Self::set_up_roles(&organization);
+		let _ = <access::Module<T>>::set_parent_for_role(organization.admin_role, Some(Self::admin_role()));
+		Organizations::<T>::insert(LEGACY_ORG, organization);
+		OrganizationCount::mutate(|count| *count = (*count).max(LEGACY_ORG.saturating_add(1)));
+
+		// Move the pot first so the credit migrated below is checked against the funds held for it
+		let legacy_pot: T::AccountId = PALLET_ID.into_account();
+		let _ = T::Currency::transfer(
+			&legacy_pot,
+			&Self::account_id(LEGACY_ORG),
+			T::Currency::free_balance(&legacy_pot),
+			ExistenceRequirement::AllowDeath,
+		);
+
+		let creditors: Vec<(T::AccountId, BalanceOf<T>)> =
+			StorageKeyIterator::<T::AccountId, BalanceOf<T>, Blake2_128Concat>::new(b"Timekeeper", b"Creditors")
+				.drain()
+				.collect();
+		let count = creditors.len() as Weight;
+		for (account, credit) in creditors {
+			Self::add_credit(LEGACY_ORG, &account, credit);
+		}
+
+		let entered = Cell::new(0);
+		Entered::<T>::translate::<T::Moment, _>(|_, start| {
+			entered.set(entered.get() + 1);
+			Some((LEGACY_ORG, start))
+		});
+		let entered: Weight = entered.get();
+
+		T::DbWeight::get().reads_writes(16 + count * 6 + entered, 16 + count * 6 + entered)
+	}
+
+	/// Credit for `time`, in milliseconds, worked at `rate`
+	pub fn calculate_credit(time: T::Moment, rate: RateSpec<BalanceOf<T>>) -> BalanceOf<T> {
+		Self::calculate_credit_with_remainder(time, rate, FixedU128::from_inner(0)).0
+	}
+
+	/// Credit for `time`, in milliseconds, worked at `rate` plus the fraction of a unit carried
+	/// over from earlier credit. Returns the whole credit and the fraction left over. Salaries
+	/// aren't paid for time worked so earn nothing here.
+	///
+	/// The fraction is rounded up, so splitting a shift never pays less than working it in one go.
+	pub fn calculate_credit_with_remainder(
+		time: T::Moment,
+		rate: RateSpec<BalanceOf<T>>,
+		remainder: FixedU128,
+	) -> (BalanceOf<T>, FixedU128) {
+		let unit = match rate.unit_millisecs() {
+			Some(unit) => unit,
+			None => return (Zero::zero(), remainder),
+		};
+		let numerator = time.saturated_into::<u128>().saturating_mul(rate.amount().saturated_into::<u128>());
+		let whole = numerator / unit;
+		let fraction = (numerator % unit)
+			.saturating_mul(FixedU128::DIV)
+			.saturating_add(unit - 1) / unit;
+		let fraction = FixedU128::from_inner(fraction).saturating_add(remainder).into_inner();
+		let credit = whole.saturating_add(fraction / FixedU128::DIV);
+		(credit.saturated_into(), FixedU128::from_inner(fraction % FixedU128::DIV))
 	}
 }
 
+impl<T: Trait> frame_support::unsigned::ValidateUnsigned for Module<T> {
+	type Call = Call<T>;
+
+	/// Only punches signed by the worker and an active device it is allowed to punch on, which
+	/// enter a worker who is out or exit a worker who is in, enter the pool
+	fn validate_unsigned(_source: TransactionSource, call: &Self::Call) -> TransactionValidity {
+		if let Call::punch(punch, worker_signature, device_signature) = call {
+			let device = match Self::check_punch_signatures(&punch.worker, &punch.device, punch.nonce, &punch.encode(), worker_signature, device_signature) {
+				Ok(device) => device,
+				Err(e) => return match e {
+					Error::<T>::ErrorInvalidPunchSignature => InvalidTransaction::BadProof,
+					Error::<T>::ErrorInvalidPunchNonce => InvalidTransaction::Stale,
+					_ => InvalidTransaction::Call,
+				}.into(),
+			};
+			let applicable = match punch.kind {
+				PunchKind::Enter => Self::entered(&punch.worker).is_none() && Rates::<T>::contains_key(device.org, &punch.worker),
+				PunchKind::Exit => Self::entered(&punch.worker).map(|(org, _)| org) == Some(device.org),
+			};
+			if !applicable {
+				return InvalidTransaction::Call.into();
+			}
+			ValidTransaction::with_tag_prefix("TimekeeperPunch")
+				.priority(PUNCH_PRIORITY)
+				.and_provides((punch.worker.clone(), punch.nonce))
+				.longevity(PUNCH_LONGEVITY)
+				.propagate(true)
+				.build()
+		} else {
+			InvalidTransaction::Call.into()
+		}
+	}
+}
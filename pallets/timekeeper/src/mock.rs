@@ -1,14 +1,15 @@
 use crate::{Module, Trait};
 use sp_core::H256;
 use frame_support::{
+	assert_ok,
 	impl_outer_origin, 
 	impl_outer_event, 
 	parameter_types, 
 	weights::Weight,
-	traits::{OnInitialize, OnFinalize},
+	traits::{OnInitialize, OnFinalize, TestRandomness},
 };
 use sp_runtime::{
-	traits::{BlakeTwo256, IdentityLookup}, testing::Header, Perbill,
+	traits::{BlakeTwo256, IdentityLookup}, testing::{Header, TestSignature, UintAuthorityId}, Perbill,
 };
 
 impl_outer_origin! {
@@ -23,6 +24,7 @@ impl_outer_event! {
 	pub enum Event for Test {
 		frame_system<T>,
 		timekeeper<T>,
+		pallet_access<T>,
 		pallet_balances<T>,
 	}
 }
@@ -86,12 +88,53 @@ impl pallet_timestamp::Trait for Test {
 	type WeightInfo = ();
 }
 
+parameter_types! {
+	pub const WhitelistRole: H256 = H256::repeat_byte(0xff);
+	pub const MaxRoleDepth: u32 = 4;
+	pub const MaxChildRoles: u32 = 4;
+}
+
+impl pallet_access::Trait for Test {
+	type Event = Event;
+	type WhitelistRole = WhitelistRole;
+	type AlwaysAllowed = ();
+	type MaxRoleDepth = MaxRoleDepth;
+	type MaxChildRoles = MaxChildRoles;
+}
+
+parameter_types! {
+	pub const MaxShiftDuration: u64 = 8 * 60 * 60 * 1000;
+	pub const PayrollPeriod: u64 = 10;
+	pub const PayrollBatchSize: u32 = 2;
+	pub const AutoExitBatchSize: u32 = 2;
+	pub const RateProposalExpiry: u64 = 5;
+	pub const AutoBreakAfter: u64 = 8 * 60 * 60 * 1000;
+	pub const AutoBreakDuration: u64 = 30 * 60 * 1000;
+	pub const MaxChallengeAge: u64 = 3;
+	pub const MaxBackdating: u64 = 4 * 60 * 60 * 1000;
+	pub const MaxOfflinePunches: u32 = 4;
+}
+
 impl Trait for Test {
 	type Event = Event;
 	type Currency = Balances;
+	type MaxShiftDuration = MaxShiftDuration;
+	type PayrollPeriod = PayrollPeriod;
+	type PayrollBatchSize = PayrollBatchSize;
+	type AutoExitBatchSize = AutoExitBatchSize;
+	type RateProposalExpiry = RateProposalExpiry;
+	type AutoBreakAfter = AutoBreakAfter;
+	type AutoBreakDuration = AutoBreakDuration;
+	type Public = UintAuthorityId;
+	type Signature = TestSignature;
+	type Randomness = TestRandomness;
+	type MaxChallengeAge = MaxChallengeAge;
+	type MaxBackdating = MaxBackdating;
+	type MaxOfflinePunches = MaxOfflinePunches;
 }
 
 pub type TimeKeeperModule = Module<Test>;
+pub type AccessModule = pallet_access::Module<Test>;
 pub type System = frame_system::Module<Test>;
 pub type Balances = pallet_balances::Module<Test>;
 pub type Timestamp = pallet_timestamp::Module<Test>;
@@ -104,13 +147,24 @@ pub fn new_test_ext() -> sp_io::TestExternalities {
 		balances: vec![(200, 500)],
     }.assimilate_storage(&mut t).unwrap();
 
-    frame_system::GenesisConfig::default().assimilate_storage::<Test>(&mut t).unwrap();
+    timekeeper::GenesisConfig::default().assimilate_storage::<Test>(&mut t).unwrap();
     
-	let mut t: sp_io::TestExternalities = frame_system::GenesisConfig::default().build_storage::<Test>().unwrap().into();
+	let mut t: sp_io::TestExternalities = t.into();
     t.execute_with(|| System::set_block_number(1) );
     t
 }
 
+/// The organization created by `setup_roles`
+pub const ORG: u32 = 0;
+
+/// Make `admin` the administrator and a registrar of a new organization
+pub fn setup_roles(admin: u64) {
+	assert_ok!(TimeKeeperModule::setup(Origin::root(), admin));
+	assert_ok!(TimeKeeperModule::create_organization(Origin::signed(admin), admin));
+	let registrar = TimeKeeperModule::organizations(ORG).unwrap().registrar_role;
+	assert_ok!(AccessModule::grant_role(Origin::signed(admin), registrar, admin, None));
+}
+
 pub fn last_event() -> Event {
     System::events().last().unwrap().event.clone()
 }
@@ -1,58 +1,112 @@
 use crate::{
 	RawEvent, 
+	BreakRecord,
+	Challenge,
+	CorrectionRequest,
+	CorrectionStatus,
+	Device,
 	Error,
+	PayPolicy,
+	Project,
+	OfflinePunch,
+	PALLET_ID,
+	Punch,
+	PunchKind,
+	RateProposal,
+	RateSpec,
+	Releases,
+	SettlementMode,
+	ShiftRecord,
+	StorageVersion,
+	ShiftSegment,
+	ShiftStatus,
 	mock::*
 };
-use frame_support::{assert_ok, assert_noop, assert_err};
+use codec::Encode;
+use frame_support::{assert_ok, assert_noop, assert_err, traits::{OnInitialize, OnRuntimeUpgrade}, unsigned::ValidateUnsigned};
+use frame_support::{Blake2_128Concat, StorageHasher, StorageValue};
+use frame_support::storage::migration::{get_storage_value, put_storage_value};
+use sp_core::H256;
+use sp_runtime::{
+	FixedPointNumber, FixedU128, Perbill,
+	traits::AccountIdConversion,
+	testing::{TestSignature, UintAuthorityId},
+	transaction_validity::{InvalidTransaction, TransactionSource},
+};
 
 const BOB : u64 = 101;
 const ALICE : u64 = 100;
 const CHARLIE : u64 = 102;
+const DAVE : u64 = 103;
+const FUNDER : u64 = 200;
 const RATE : u64 = 10;
 const BLOCK_JUMP : u64 = 10;
+const HOUR : u64 = 60 * 60 * 1000;
+const DAY : u64 = 24 * HOUR;
+const DEVICE : u64 = 300;
+const SITE : u32 = 1;
 
 #[test]
 fn it_works_registering_a_user() {
 	new_test_ext().execute_with(|| {
+		setup_roles(ALICE);
 		// Register user BOB at RATE
-		assert_ok!(TimeKeeperModule::register_account(Origin::signed(ALICE), BOB, Some(RATE)));
+		assert_ok!(TimeKeeperModule::register_account(Origin::signed(ALICE), ORG, BOB, Some(RateSpec::Hourly(RATE))));
 		// We shouldn't be able to register the same account again
-		assert_err!(TimeKeeperModule::register_account(Origin::signed(ALICE), BOB, Some(RATE)), 
-					"trying to register an existing account");
+		assert_noop!(TimeKeeperModule::register_account(Origin::signed(ALICE), ORG, BOB, Some(RateSpec::Hourly(RATE))),
+					Error::<Test>::ErrorAlreadyRegistered);
 		// Check we have set the RATE for BOB
-		assert_eq!(TimeKeeperModule::rates(&BOB), Some(RATE));
+		assert_eq!(TimeKeeperModule::rates(ORG, &BOB), Some(RateSpec::Hourly(RATE)));
 		// Confirm we sent the event out for BOB at RATE set
-		assert_eq!(last_event(), Event::timekeeper(RawEvent::AccountRegistered(BOB, Some(RATE))));
+		assert_eq!(last_event(), Event::timekeeper(RawEvent::AccountRegistered(ORG, BOB, Some(RateSpec::Hourly(RATE)))));
+	});
+}
+
+#[test]
+fn it_whitelists_registered_accounts() {
+	new_test_ext().execute_with(|| {
+		setup_roles(ALICE);
+		assert!(!AccessModule::has_role(WhitelistRole::get(), BOB));
+		assert_ok!(TimeKeeperModule::register_account(Origin::signed(ALICE), ORG, BOB, Some(RateSpec::Hourly(RATE))));
+		assert!(AccessModule::has_role(WhitelistRole::get(), BOB));
+		// Timekeeper and organization administrators are whitelisted too
+		assert!(AccessModule::has_role(WhitelistRole::get(), ALICE));
+		assert_ok!(TimeKeeperModule::create_organization(Origin::signed(ALICE), CHARLIE));
+		assert!(AccessModule::has_role(WhitelistRole::get(), CHARLIE));
+		// Whitelisted accounts can still be registered with other organizations
+		assert_ok!(TimeKeeperModule::create_organization(Origin::signed(ALICE), ALICE));
+		assert_ok!(TimeKeeperModule::register_account(Origin::signed(ALICE), ORG + 2, BOB, Some(RateSpec::Hourly(RATE))));
 	});
 }
 
 #[test]
 fn it_checks_in_and_out() {
 	new_test_ext().execute_with(|| {
+		setup_roles(ALICE);
 		// Register BOB at RATE
-		assert_ok!(TimeKeeperModule::register_account(Origin::signed(ALICE), BOB, Some(RATE)));
+		assert_ok!(TimeKeeperModule::register_account(Origin::signed(ALICE), ORG, BOB, Some(RateSpec::Hourly(RATE))));
 		// Confirm we sent the event out for BOB at RATE
-		assert_eq!(last_event(), Event::timekeeper(RawEvent::AccountRegistered(BOB, Some(RATE))));
+		assert_eq!(last_event(), Event::timekeeper(RawEvent::AccountRegistered(ORG, BOB, Some(RateSpec::Hourly(RATE)))));
 		// BOB enters
-		assert_ok!(TimeKeeperModule::enter_account(Origin::signed(BOB)));
+		assert_ok!(TimeKeeperModule::enter_account(Origin::signed(BOB), ORG, None));
 		// BOB trys to enter again
-		assert_noop!(TimeKeeperModule::enter_account(Origin::signed(BOB)), Error::<Test>::FailedToEnter);
+		assert_noop!(TimeKeeperModule::enter_account(Origin::signed(BOB), ORG, None), Error::<Test>::FailedToEnter);
 		// CHARLIE trys to enter
-		assert_err!(TimeKeeperModule::enter_account(Origin::signed(CHARLIE)), "account not registered");
+		assert_err!(TimeKeeperModule::enter_account(Origin::signed(CHARLIE), ORG, None), "account not registered");
 		// Confirm we sent the event for BOB entered
-		assert_eq!(last_event(), Event::timekeeper(RawEvent::AccountEntered(BOB)));
+		assert_eq!(last_event(), Event::timekeeper(RawEvent::AccountEntered(ORG, BOB)));
 		// Move to block
 		run_to_block(BLOCK_JUMP);
 		// BOB exits
-		assert_ok!(TimeKeeperModule::exit_account(Origin::signed(BOB)));
+		assert_ok!(TimeKeeperModule::exit_account(Origin::signed(BOB), None));
 		// BOB trys to exit again
-		assert_noop!(TimeKeeperModule::exit_account(Origin::signed(BOB)), Error::<Test>::FailedToExit);
+		assert_noop!(TimeKeeperModule::exit_account(Origin::signed(BOB), None), Error::<Test>::FailedToExit);
 		// CHARLIE trys to exit
-		assert_err!(TimeKeeperModule::exit_account(Origin::signed(CHARLIE)), "account not registered");
+		assert_noop!(TimeKeeperModule::exit_account(Origin::signed(CHARLIE), None), Error::<Test>::FailedToExit);
 		// Confirm we sent the event for BOB exited
-		assert_eq!(last_event(), Event::timekeeper(RawEvent::AccountExited(BOB)));
+		assert_eq!(last_event(), Event::timekeeper(RawEvent::AccountExited(ORG, BOB)));
 		// Check that BOB is now a creditor and should have credit of RATE * BLOCK_JUMP
-		assert_eq!(TimeKeeperModule::creditors(&BOB), Some(RATE * BLOCK_JUMP));
+		assert_eq!(TimeKeeperModule::creditors(ORG, &BOB), Some(RATE * BLOCK_JUMP));
 	});
 }
 
@@ -60,6 +114,1201 @@ fn it_checks_in_and_out() {
 fn check_credit_calculation() {
 	let min: u32 = 60_000;
 	let rate = 10; // 1 x 10e-11 Units
-	assert_eq!(TimeKeeperModule::calculate_credit((60*min).into(), rate.into()), rate);
-	assert_eq!(TimeKeeperModule::calculate_credit((30*min).into(), rate.into()), rate / 2);
+	assert_eq!(TimeKeeperModule::calculate_credit((60*min).into(), RateSpec::Hourly(rate)), rate);
+	assert_eq!(TimeKeeperModule::calculate_credit((30*min).into(), RateSpec::Hourly(rate)), rate / 2);
+}
+
+#[test]
+fn it_exits_automatically_after_max_shift_duration() {
+	new_test_ext().execute_with(|| {
+		setup_roles(ALICE);
+		assert_ok!(TimeKeeperModule::register_account(Origin::signed(ALICE), ORG, BOB, Some(RateSpec::Hourly(RATE))));
+		Timestamp::set_timestamp(HOUR);
+		assert_ok!(TimeKeeperModule::enter_account(Origin::signed(BOB), ORG, None));
+		// Still within the maximum shift, BOB stays entered
+		Timestamp::set_timestamp(HOUR + MaxShiftDuration::get());
+		TimeKeeperModule::on_initialize(2);
+		assert!(TimeKeeperModule::entered(&BOB).is_some());
+		// Past the maximum shift, BOB is exited and credited for the maximum only
+		Timestamp::set_timestamp(3 * HOUR + MaxShiftDuration::get());
+		TimeKeeperModule::on_initialize(3);
+		assert_eq!(TimeKeeperModule::entered(&BOB), None);
+		assert_eq!(last_event(), Event::timekeeper(RawEvent::AutoExited(ORG, BOB, MaxShiftDuration::get())));
+		assert_eq!(TimeKeeperModule::creditors(ORG, &BOB), Some(8 * RATE));
+	});
+}
+
+#[test]
+fn it_exits_overdue_accounts_in_batches() {
+	new_test_ext().execute_with(|| {
+		setup_roles(ALICE);
+		for account in &[BOB, CHARLIE, DAVE] {
+			assert_ok!(TimeKeeperModule::register_account(Origin::signed(ALICE), ORG, *account, Some(RateSpec::Hourly(RATE))));
+			assert_ok!(TimeKeeperModule::enter_account(Origin::signed(*account), ORG, None));
+		}
+		Timestamp::set_timestamp(2 * HOUR + MaxShiftDuration::get());
+		// Only AutoExitBatchSize accounts are checked in a block
+		TimeKeeperModule::on_initialize(2);
+		let still_entered = || [BOB, CHARLIE, DAVE].iter().filter(|a| TimeKeeperModule::entered(a).is_some()).count();
+		assert_eq!(still_entered(), 1);
+		// The next block carries on with the rest
+		TimeKeeperModule::on_initialize(3);
+		assert_eq!(still_entered(), 0);
+		for account in &[BOB, CHARLIE, DAVE] {
+			assert_eq!(TimeKeeperModule::creditors(ORG, account), Some(8 * RATE));
+		}
+	});
+}
+
+#[test]
+fn it_overrides_max_shift_duration_for_an_account() {
+	new_test_ext().execute_with(|| {
+		setup_roles(ALICE);
+		assert_ok!(TimeKeeperModule::register_account(Origin::signed(ALICE), ORG, BOB, Some(RateSpec::Hourly(RATE))));
+		// Only the administrator can override
+		assert_noop!(TimeKeeperModule::set_max_shift_duration(Origin::signed(BOB), ORG, BOB, Some(HOUR)),
+					Error::<Test>::ErrorAdminRoleRequired);
+		assert_ok!(TimeKeeperModule::set_max_shift_duration(Origin::signed(ALICE), ORG, BOB, Some(HOUR)));
+		assert_eq!(TimeKeeperModule::max_shift_duration(ORG, &BOB), HOUR);
+		assert_eq!(TimeKeeperModule::max_shift_duration(ORG, &CHARLIE), MaxShiftDuration::get());
+		assert_ok!(TimeKeeperModule::enter_account(Origin::signed(BOB), ORG, None));
+		Timestamp::set_timestamp(2 * HOUR);
+		TimeKeeperModule::on_initialize(2);
+		assert_eq!(TimeKeeperModule::entered(&BOB), None);
+		assert_eq!(last_event(), Event::timekeeper(RawEvent::AutoExited(ORG, BOB, HOUR)));
+		assert_eq!(TimeKeeperModule::creditors(ORG, &BOB), Some(RATE));
+	});
+}
+
+#[test]
+fn it_records_shifts_in_the_timesheet() {
+	new_test_ext().execute_with(|| {
+		setup_roles(ALICE);
+		assert_ok!(TimeKeeperModule::register_account(Origin::signed(ALICE), ORG, BOB, Some(RateSpec::Hourly(RATE))));
+		assert_eq!(TimeKeeperModule::shift_count(&BOB), 0);
+		// First shift of two hours, exited by BOB
+		assert_ok!(TimeKeeperModule::enter_account(Origin::signed(BOB), ORG, None));
+		Timestamp::set_timestamp(2 * HOUR);
+		assert_ok!(TimeKeeperModule::exit_account(Origin::signed(BOB), None));
+		// Second shift exited automatically
+		Timestamp::set_timestamp(3 * HOUR);
+		assert_ok!(TimeKeeperModule::enter_account(Origin::signed(BOB), ORG, None));
+		Timestamp::set_timestamp(12 * HOUR);
+		TimeKeeperModule::on_initialize(2);
+		// Check both shifts are in the timesheet
+		assert_eq!(TimeKeeperModule::shift_count(&BOB), 2);
+		assert_eq!(TimeKeeperModule::timesheets(&BOB, 0), Some(ShiftRecord {
+			org: ORG,
+			start: 0,
+			end: 2 * HOUR,
+			duration: 2 * HOUR,
+			rate: Some(RateSpec::Hourly(RATE)),
+			credit: 2 * RATE,
+			auto_exited: false,
+			segments: vec![ShiftSegment { start: 0, end: 2 * HOUR, multiplier: 100, credit: 2 * RATE }],
+			breaks: vec![],
+			project: None,
+			status: ShiftStatus::Approved,
+			entered_by: None,
+			exited_by: None,
+		}));
+		assert_eq!(TimeKeeperModule::timesheets(&BOB, 1), Some(ShiftRecord {
+			org: ORG,
+			start: 3 * HOUR,
+			end: 11 * HOUR,
+			duration: 8 * HOUR,
+			rate: Some(RateSpec::Hourly(RATE)),
+			credit: 8 * RATE,
+			auto_exited: true,
+			segments: vec![ShiftSegment { start: 3 * HOUR, end: 11 * HOUR, multiplier: 100, credit: 8 * RATE }],
+			breaks: vec![],
+			project: None,
+			status: ShiftStatus::Approved,
+			entered_by: None,
+			exited_by: None,
+		}));
+		assert_eq!(TimeKeeperModule::timesheets(&BOB, 2), None);
+		assert_eq!(TimeKeeperModule::creditors(ORG, &BOB), Some(10 * RATE));
+	});
+}
+
+#[test]
+fn it_pays_creditors_in_batches_every_payroll_period() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(TimeKeeperModule::deposit(Origin::signed(FUNDER), ORG, 100));
+		TimeKeeperModule::add_credit(ORG, &BOB, 10);
+		TimeKeeperModule::add_credit(ORG, &CHARLIE, 20);
+		TimeKeeperModule::add_credit(ORG, &DAVE, 30);
+		// Crediting again doesn't queue BOB twice
+		TimeKeeperModule::add_credit(ORG, &BOB, 5);
+		assert_eq!(TimeKeeperModule::payroll_queue_tail(), 3);
+		// Nothing is paid before the payroll period
+		TimeKeeperModule::on_initialize(9);
+		assert_eq!(Balances::free_balance(&BOB), 0);
+		// The first batch is paid when the period starts
+		TimeKeeperModule::on_initialize(10);
+		assert_eq!(Balances::free_balance(&BOB), 15);
+		assert_eq!(Balances::free_balance(&CHARLIE), 20);
+		assert_eq!(Balances::free_balance(&DAVE), 0);
+		assert_eq!(TimeKeeperModule::creditors(ORG, &BOB), None);
+		assert_eq!(TimeKeeperModule::payroll_end(), Some(3));
+		// Credit earned during the run waits for the next run
+		TimeKeeperModule::add_credit(ORG, &BOB, 7);
+		// The rest is paid in the following block
+		TimeKeeperModule::on_initialize(11);
+		assert_eq!(Balances::free_balance(&DAVE), 30);
+		assert_eq!(last_event(), Event::timekeeper(RawEvent::PayrollCompleted));
+		assert_eq!(TimeKeeperModule::payroll_end(), None);
+		assert_eq!(TimeKeeperModule::creditors(ORG, &BOB), Some(7));
+		assert_eq!(Balances::free_balance(&TimeKeeperModule::account_id(ORG)), 35);
+		// BOB is paid on the next run
+		TimeKeeperModule::on_initialize(20);
+		assert_eq!(Balances::free_balance(&BOB), 22);
+		assert_eq!(TimeKeeperModule::creditors(ORG, &BOB), None);
+	});
+}
+
+#[test]
+fn it_keeps_credit_when_payment_fails() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(TimeKeeperModule::deposit(Origin::signed(FUNDER), ORG, 10));
+		TimeKeeperModule::add_credit(ORG, &BOB, 50);
+		TimeKeeperModule::on_initialize(10);
+		// The pallet account can't cover the credit, which is kept and queued again
+		assert_eq!(TimeKeeperModule::creditors(ORG, &BOB), Some(50));
+		assert_eq!(TimeKeeperModule::payroll_queued(ORG, &BOB), Some(1));
+		assert!(System::events().iter().any(|r| r.event == Event::timekeeper(RawEvent::PaymentFailed(ORG, BOB, 50))));
+		// Once funded BOB is paid on the next run
+		assert_ok!(TimeKeeperModule::deposit(Origin::signed(FUNDER), ORG, 40));
+		TimeKeeperModule::on_initialize(20);
+		assert_eq!(Balances::free_balance(&BOB), 50);
+		assert_eq!(TimeKeeperModule::creditors(ORG, &BOB), None);
+	});
+}
+
+#[test]
+fn it_updates_rate_with_admin_and_account_holder_consent() {
+	new_test_ext().execute_with(|| {
+		setup_roles(ALICE);
+		assert_ok!(TimeKeeperModule::register_account(Origin::signed(ALICE), ORG, BOB, Some(RateSpec::Hourly(RATE))));
+		// CHARLIE is neither an administrator nor the account holder
+		assert_noop!(TimeKeeperModule::update_rate_for_account(Origin::signed(CHARLIE), ORG, BOB, Some(RateSpec::Hourly(2 * RATE))),
+					Error::<Test>::ErrorAdminOrAccountHolderRequired);
+		// ALICE proposes a new rate for BOB
+		assert_ok!(TimeKeeperModule::update_rate_for_account(Origin::signed(ALICE), ORG, BOB, Some(RateSpec::Hourly(2 * RATE))));
+		assert_eq!(last_event(), Event::timekeeper(RawEvent::RateProposed(ALICE, ORG, BOB, Some(RateSpec::Hourly(2 * RATE)))));
+		assert_eq!(TimeKeeperModule::rate_proposals(ORG, &BOB), Some(RateProposal {
+			proposer: ALICE,
+			rate: Some(RateSpec::Hourly(2 * RATE)),
+			expires: 1 + RateProposalExpiry::get(),
+		}));
+		// Only one proposal at a time
+		assert_noop!(TimeKeeperModule::update_rate_for_account(Origin::signed(BOB), ORG, BOB, Some(RateSpec::Hourly(3 * RATE))),
+					Error::<Test>::ErrorRateProposalExists);
+		// The rate isn't applied until BOB accepts
+		assert_eq!(TimeKeeperModule::rates(ORG, &BOB), Some(RateSpec::Hourly(RATE)));
+		assert_noop!(TimeKeeperModule::accept_rate(Origin::signed(ALICE), ORG, BOB), Error::<Test>::ErrorAccountHolderRequired);
+		assert_ok!(TimeKeeperModule::accept_rate(Origin::signed(BOB), ORG, BOB));
+		assert_eq!(last_event(), Event::timekeeper(RawEvent::AccountUpdated(ORG, BOB, Some(RateSpec::Hourly(2 * RATE)))));
+		assert_eq!(TimeKeeperModule::rates(ORG, &BOB), Some(RateSpec::Hourly(2 * RATE)));
+		assert_eq!(TimeKeeperModule::rate_proposals(ORG, &BOB), None);
+		// BOB proposes a rate which the administrator has to accept
+		assert_ok!(TimeKeeperModule::update_rate_for_account(Origin::signed(BOB), ORG, BOB, Some(RateSpec::Hourly(3 * RATE))));
+		assert_noop!(TimeKeeperModule::accept_rate(Origin::signed(BOB), ORG, BOB), Error::<Test>::ErrorAdminRoleRequired);
+		assert_ok!(TimeKeeperModule::accept_rate(Origin::signed(ALICE), ORG, BOB));
+		assert_eq!(TimeKeeperModule::rates(ORG, &BOB), Some(RateSpec::Hourly(3 * RATE)));
+	});
+}
+
+#[test]
+fn it_rejects_cancels_and_expires_rate_proposals() {
+	new_test_ext().execute_with(|| {
+		setup_roles(ALICE);
+		assert_ok!(TimeKeeperModule::register_account(Origin::signed(ALICE), ORG, BOB, Some(RateSpec::Hourly(RATE))));
+		// BOB rejects the rate proposed by ALICE
+		assert_ok!(TimeKeeperModule::update_rate_for_account(Origin::signed(ALICE), ORG, BOB, Some(RateSpec::Hourly(RATE / 2))));
+		assert_ok!(TimeKeeperModule::reject_rate(Origin::signed(BOB), ORG, BOB));
+		assert_eq!(last_event(), Event::timekeeper(RawEvent::RateRejected(BOB, ORG, BOB)));
+		assert_eq!(TimeKeeperModule::rate_proposals(ORG, &BOB), None);
+		// Only the proposer can cancel
+		assert_ok!(TimeKeeperModule::update_rate_for_account(Origin::signed(ALICE), ORG, BOB, Some(RateSpec::Hourly(RATE / 2))));
+		assert_noop!(TimeKeeperModule::cancel_rate_proposal(Origin::signed(BOB), ORG, BOB), Error::<Test>::ErrorProposerRequired);
+		assert_ok!(TimeKeeperModule::cancel_rate_proposal(Origin::signed(ALICE), ORG, BOB));
+		assert_eq!(last_event(), Event::timekeeper(RawEvent::RateProposalCancelled(ALICE, ORG, BOB)));
+		// An expired proposal can't be accepted but can be replaced
+		assert_ok!(TimeKeeperModule::update_rate_for_account(Origin::signed(ALICE), ORG, BOB, Some(RateSpec::Hourly(RATE / 2))));
+		System::set_block_number(2 + RateProposalExpiry::get());
+		assert_noop!(TimeKeeperModule::accept_rate(Origin::signed(BOB), ORG, BOB), Error::<Test>::ErrorRateProposalExpired);
+		assert_ok!(TimeKeeperModule::update_rate_for_account(Origin::signed(BOB), ORG, BOB, Some(RateSpec::Hourly(2 * RATE))));
+		assert_ok!(TimeKeeperModule::accept_rate(Origin::signed(ALICE), ORG, BOB));
+		assert_eq!(TimeKeeperModule::rates(ORG, &BOB), Some(RateSpec::Hourly(2 * RATE)));
+	});
+}
+
+#[test]
+fn it_withdraws_part_of_the_credit() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(TimeKeeperModule::deposit(Origin::signed(FUNDER), ORG, 500));
+		TimeKeeperModule::add_credit(ORG, &BOB, 100);
+		// Withdrawing 10 leaves 90 in credit
+		assert_ok!(TimeKeeperModule::withdraw(Origin::signed(BOB), ORG, 10));
+		assert_eq!(last_event(), Event::timekeeper(RawEvent::Withdrawn(ORG, BOB, 10, 90)));
+		assert_eq!(TimeKeeperModule::creditors(ORG, &BOB), Some(90));
+		assert_eq!(Balances::free_balance(&BOB), 10);
+		assert_ok!(TimeKeeperModule::withdraw(Origin::signed(BOB), ORG, 40));
+		assert_eq!(TimeKeeperModule::creditors(ORG, &BOB), Some(50));
+		assert_eq!(Balances::free_balance(&BOB), 50);
+	});
+}
+
+#[test]
+fn it_fails_to_overdraw_credit() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(TimeKeeperModule::deposit(Origin::signed(FUNDER), ORG, 500));
+		// No credit at all
+		assert_noop!(TimeKeeperModule::withdraw(Origin::signed(BOB), ORG, 10), Error::<Test>::FailedCredit);
+		assert_noop!(TimeKeeperModule::withdraw_all(Origin::signed(BOB), ORG), Error::<Test>::FailedCredit);
+		TimeKeeperModule::add_credit(ORG, &BOB, 100);
+		// More than the credit, the credit is left untouched
+		assert_noop!(TimeKeeperModule::withdraw(Origin::signed(BOB), ORG, 101), Error::<Test>::FailedInsufficientCredit);
+		assert_noop!(TimeKeeperModule::withdraw(Origin::signed(BOB), ORG, 0), Error::<Test>::FailedToWithdraw);
+		assert_eq!(TimeKeeperModule::creditors(ORG, &BOB), Some(100));
+		assert_eq!(Balances::free_balance(&BOB), 0);
+	});
+}
+
+#[test]
+fn it_withdraws_the_exact_credit() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(TimeKeeperModule::deposit(Origin::signed(FUNDER), ORG, 500));
+		TimeKeeperModule::add_credit(ORG, &BOB, 100);
+		assert_ok!(TimeKeeperModule::withdraw(Origin::signed(BOB), ORG, 100));
+		assert_eq!(last_event(), Event::timekeeper(RawEvent::Withdrawn(ORG, BOB, 100, 0)));
+		assert_eq!(TimeKeeperModule::creditors(ORG, &BOB), None);
+		assert_eq!(Balances::free_balance(&BOB), 100);
+		// Nothing left to withdraw
+		assert_noop!(TimeKeeperModule::withdraw(Origin::signed(BOB), ORG, 1), Error::<Test>::FailedCredit);
+	});
+}
+
+#[test]
+fn it_withdraws_all_the_credit() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(TimeKeeperModule::deposit(Origin::signed(FUNDER), ORG, 500));
+		TimeKeeperModule::add_credit(ORG, &BOB, 100);
+		assert_ok!(TimeKeeperModule::withdraw(Origin::signed(BOB), ORG, 30));
+		assert_ok!(TimeKeeperModule::withdraw_all(Origin::signed(BOB), ORG));
+		assert_eq!(last_event(), Event::timekeeper(RawEvent::Withdrawn(ORG, BOB, 70, 0)));
+		assert_eq!(TimeKeeperModule::creditors(ORG, &BOB), None);
+		assert_eq!(Balances::free_balance(&BOB), 100);
+	});
+}
+
+#[test]
+fn it_keeps_the_credit_when_the_transfer_fails() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(TimeKeeperModule::deposit(Origin::signed(FUNDER), ORG, 50));
+		TimeKeeperModule::add_credit(ORG, &BOB, 100);
+		assert_noop!(TimeKeeperModule::withdraw(Origin::signed(BOB), ORG, 60), Error::<Test>::ErrorUnderfunded);
+		assert_eq!(TimeKeeperModule::creditors(ORG, &BOB), Some(100));
+		assert_eq!(Balances::free_balance(&BOB), 0);
+	});
+}
+
+#[test]
+fn it_tracks_outstanding_credit() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(TimeKeeperModule::deposit(Origin::signed(FUNDER), ORG, 100));
+		TimeKeeperModule::add_credit(ORG, &BOB, 30);
+		TimeKeeperModule::add_credit(ORG, &CHARLIE, 50);
+		assert_eq!(TimeKeeperModule::total_outstanding(ORG), 80);
+		assert!(TimeKeeperModule::is_solvent(ORG));
+		assert_eq!(TimeKeeperModule::surplus(ORG), 20);
+		// Withdrawals reduce the outstanding credit
+		assert_ok!(TimeKeeperModule::withdraw(Origin::signed(BOB), ORG, 10));
+		assert_eq!(TimeKeeperModule::total_outstanding(ORG), 70);
+		// Payments reduce the outstanding credit
+		TimeKeeperModule::on_initialize(10);
+		assert_eq!(TimeKeeperModule::total_outstanding(ORG), 0);
+		assert_eq!(TimeKeeperModule::pot_balance(ORG), 20);
+		// Credit above the funds makes the pallet account underfunded
+		TimeKeeperModule::add_credit(ORG, &BOB, 50);
+		assert_eq!(last_event(), Event::timekeeper(RawEvent::Underfunded(ORG, 20, 50)));
+		assert!(!TimeKeeperModule::is_solvent(ORG));
+		assert_eq!(TimeKeeperModule::shortfall(ORG), 30);
+		assert_noop!(TimeKeeperModule::withdraw(Origin::signed(BOB), ORG, 30), Error::<Test>::ErrorUnderfunded);
+	});
+}
+
+#[test]
+fn it_keeps_the_ratio_of_a_payroll_run_spread_over_blocks() {
+	new_test_ext().execute_with(|| {
+		setup_roles(ALICE);
+		assert_ok!(TimeKeeperModule::set_settlement_mode(Origin::signed(ALICE), ORG, SettlementMode::ProRata));
+		assert_ok!(TimeKeeperModule::deposit(Origin::signed(FUNDER), ORG, 50));
+		TimeKeeperModule::add_credit(ORG, &BOB, 40);
+		TimeKeeperModule::add_credit(ORG, &CHARLIE, 30);
+		TimeKeeperModule::add_credit(ORG, &DAVE, 30);
+		// Only PayrollBatchSize accounts are paid in a block
+		TimeKeeperModule::on_initialize(10);
+		assert_eq!(TimeKeeperModule::payroll_ratio(ORG), Some(Perbill::from_percent(50)));
+		assert_eq!(Balances::free_balance(&BOB), 20);
+		assert_eq!(Balances::free_balance(&CHARLIE), 15);
+		// Funds deposited during the run don't change what it pays
+		assert_ok!(TimeKeeperModule::deposit(Origin::signed(FUNDER), ORG, 100));
+		TimeKeeperModule::on_initialize(11);
+		assert_eq!(Balances::free_balance(&DAVE), 15);
+		assert_eq!(last_event(), Event::timekeeper(RawEvent::PayrollCompleted));
+		assert_eq!(TimeKeeperModule::payroll_ratio(ORG), None);
+	});
+}
+
+#[test]
+fn it_reclaims_surplus() {
+	new_test_ext().execute_with(|| {
+		setup_roles(ALICE);
+		assert_ok!(TimeKeeperModule::deposit(Origin::signed(FUNDER), ORG, 100));
+		TimeKeeperModule::add_credit(ORG, &BOB, 60);
+		// Only the administrator can reclaim
+		assert_noop!(TimeKeeperModule::reclaim_surplus(Origin::signed(BOB), ORG, 10), Error::<Test>::ErrorAdminRoleRequired);
+		// Nothing owed to creditors can be reclaimed
+		assert_noop!(TimeKeeperModule::reclaim_surplus(Origin::signed(ALICE), ORG, 41), Error::<Test>::ErrorInsufficientSurplus);
+		assert_ok!(TimeKeeperModule::reclaim_surplus(Origin::signed(ALICE), ORG, 40));
+		assert_eq!(last_event(), Event::timekeeper(RawEvent::SurplusReclaimed(ALICE, ORG, 40)));
+		assert_eq!(Balances::free_balance(&ALICE), 40);
+		assert_eq!(TimeKeeperModule::pot_balance(ORG), 60);
+		assert_eq!(TimeKeeperModule::surplus(ORG), 0);
+	});
+}
+
+#[test]
+fn it_pays_pro_rata_when_underfunded() {
+	new_test_ext().execute_with(|| {
+		setup_roles(ALICE);
+		assert_noop!(TimeKeeperModule::set_settlement_mode(Origin::signed(BOB), ORG, SettlementMode::ProRata),
+					Error::<Test>::ErrorAdminRoleRequired);
+		assert_ok!(TimeKeeperModule::set_settlement_mode(Origin::signed(ALICE), ORG, SettlementMode::ProRata));
+		assert_ok!(TimeKeeperModule::deposit(Origin::signed(FUNDER), ORG, 50));
+		TimeKeeperModule::add_credit(ORG, &BOB, 60);
+		TimeKeeperModule::add_credit(ORG, &CHARLIE, 40);
+		// Half of the credit owed can be paid, so everyone gets half
+		TimeKeeperModule::on_initialize(10);
+		assert!(System::events().iter().any(|r| r.event == Event::timekeeper(RawEvent::PartialPayroll(ORG, Perbill::from_percent(50)))));
+		assert_eq!(last_event(), Event::timekeeper(RawEvent::PayrollCompleted));
+		assert_eq!(Balances::free_balance(&BOB), 30);
+		assert_eq!(Balances::free_balance(&CHARLIE), 20);
+		// The rest is carried over to the next run
+		assert_eq!(TimeKeeperModule::creditors(ORG, &BOB), Some(30));
+		assert_eq!(TimeKeeperModule::creditors(ORG, &CHARLIE), Some(20));
+		assert_eq!(TimeKeeperModule::total_outstanding(ORG), 50);
+		assert_eq!(TimeKeeperModule::payroll_ratio(ORG), None);
+		// Once funded the carried over credit is paid in full
+		assert_ok!(TimeKeeperModule::deposit(Origin::signed(FUNDER), ORG, 50));
+		TimeKeeperModule::on_initialize(20);
+		assert_eq!(Balances::free_balance(&BOB), 60);
+		assert_eq!(Balances::free_balance(&CHARLIE), 40);
+		assert_eq!(TimeKeeperModule::total_outstanding(ORG), 0);
+	});
+}
+
+#[test]
+fn it_calculates_credit_without_overflow() {
+	// The product of time and rate doesn't fit in a u64
+	let rate = u64::max_value() / 1_000;
+	assert_eq!(TimeKeeperModule::calculate_credit(8 * HOUR, RateSpec::Hourly(rate)), 8 * rate);
+	// A result too large for the balance saturates instead of wrapping or returning zero
+	assert_eq!(TimeKeeperModule::calculate_credit(1_000_000 * HOUR, RateSpec::Hourly(rate)), u64::max_value());
+}
+
+#[test]
+fn it_carries_fractions_of_credit_over() {
+	let third = HOUR / 3;
+	let (credit, remainder) = TimeKeeperModule::calculate_credit_with_remainder(third, RateSpec::Hourly(1), FixedU128::from_inner(0));
+	assert_eq!(credit, 0);
+	let (credit, remainder) = TimeKeeperModule::calculate_credit_with_remainder(third, RateSpec::Hourly(1), remainder);
+	assert_eq!(credit, 0);
+	let (credit, remainder) = TimeKeeperModule::calculate_credit_with_remainder(third, RateSpec::Hourly(1), remainder);
+	assert_eq!(credit, 1);
+	assert!(remainder < FixedU128::saturating_from_rational(1, 1_000_000));
+
+	new_test_ext().execute_with(|| {
+		setup_roles(ALICE);
+		assert_ok!(TimeKeeperModule::register_account(Origin::signed(ALICE), ORG, BOB, Some(RateSpec::Hourly(1))));
+		// Three shifts of 20 minutes at a rate of 1 per hour earn 1
+		for shift in 0..3 {
+			Timestamp::set_timestamp(shift * HOUR);
+			assert_ok!(TimeKeeperModule::enter_account(Origin::signed(BOB), ORG, None));
+			Timestamp::set_timestamp(shift * HOUR + third);
+			assert_ok!(TimeKeeperModule::exit_account(Origin::signed(BOB), None));
+		}
+		assert_eq!(TimeKeeperModule::creditors(ORG, &BOB), Some(1));
+	});
+}
+
+#[test]
+fn splitting_a_shift_never_pays_less() {
+	// Deterministic pseudo random durations, rates and split points
+	let mut seed: u64 = 42;
+	let mut next = |max: u64| {
+		seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+		(seed >> 33) % max
+	};
+	for _ in 0..500 {
+		let duration = next(12 * HOUR) + 1;
+		let rate = match next(3) {
+			0 => RateSpec::Hourly(next(1_000_000) + 1),
+			1 => RateSpec::PerMinute(next(1_000_000) + 1),
+			_ => RateSpec::Daily(next(1_000_000) + 1),
+		};
+		let parts = next(10) + 2;
+		let continuous = TimeKeeperModule::calculate_credit(duration, rate);
+
+		let mut split = 0;
+		let mut remainder = FixedU128::from_inner(0);
+		let mut left = duration;
+		for part in 0..parts {
+			let time = if part == parts - 1 { left } else { next(left + 1) };
+			left -= time;
+			let (credit, r) = TimeKeeperModule::calculate_credit_with_remainder(time, rate, remainder);
+			split += credit;
+			remainder = r;
+		}
+		assert!(split >= continuous, "duration {} rate {:?} paid {} split, {} continuous", duration, rate, split, continuous);
+	}
+}
+
+#[test]
+fn it_calculates_credit_for_each_rate_unit() {
+	let minute = HOUR / 60;
+	assert_eq!(TimeKeeperModule::calculate_credit(HOUR, RateSpec::Hourly(RATE)), RATE);
+	assert_eq!(TimeKeeperModule::calculate_credit(HOUR, RateSpec::PerMinute(RATE)), 60 * RATE);
+	assert_eq!(TimeKeeperModule::calculate_credit(90 * minute, RateSpec::PerMinute(RATE)), 90 * RATE);
+	assert_eq!(TimeKeeperModule::calculate_credit(8 * HOUR, RateSpec::Daily(RATE)), RATE);
+	assert_eq!(TimeKeeperModule::calculate_credit(4 * HOUR, RateSpec::Daily(RATE)), RATE / 2);
+	// Salaries aren't paid for time worked
+	assert_eq!(TimeKeeperModule::calculate_credit(8 * HOUR, RateSpec::Salaried(RATE)), 0);
+}
+
+#[test]
+fn it_accrues_salary_every_payroll_period() {
+	new_test_ext().execute_with(|| {
+		setup_roles(ALICE);
+		assert_ok!(TimeKeeperModule::deposit(Origin::signed(FUNDER), ORG, 300));
+		// BOB is registered half way through the payroll period
+		System::set_block_number(5);
+		assert_ok!(TimeKeeperModule::register_account(Origin::signed(ALICE), ORG, BOB, Some(RateSpec::Salaried(100))));
+		assert_eq!(TimeKeeperModule::payroll_queued(ORG, &BOB), Some(0));
+		// Entering and exiting earns nothing more
+		assert_ok!(TimeKeeperModule::enter_account(Origin::signed(BOB), ORG, None));
+		Timestamp::set_timestamp(HOUR);
+		assert_ok!(TimeKeeperModule::exit_account(Origin::signed(BOB), None));
+		assert_eq!(TimeKeeperModule::creditors(ORG, &BOB), None);
+		// The first payroll pays half the salary
+		System::set_block_number(10);
+		TimeKeeperModule::on_initialize(10);
+		assert_eq!(Balances::free_balance(&BOB), 50);
+		// BOB stays queued and is paid the full salary on the next run
+		assert!(TimeKeeperModule::payroll_queued(ORG, &BOB).is_some());
+		System::set_block_number(20);
+		TimeKeeperModule::on_initialize(20);
+		assert_eq!(Balances::free_balance(&BOB), 150);
+		// Switching to an hourly rate credits the salary owed so far and stops the salary
+		System::set_block_number(25);
+		assert_ok!(TimeKeeperModule::update_rate_for_account(Origin::signed(ALICE), ORG, BOB, Some(RateSpec::Hourly(RATE))));
+		assert_ok!(TimeKeeperModule::accept_rate(Origin::signed(BOB), ORG, BOB));
+		assert_eq!(TimeKeeperModule::creditors(ORG, &BOB), Some(50));
+		assert_eq!(TimeKeeperModule::salary_accrued_at(ORG, &BOB), None);
+		System::set_block_number(30);
+		TimeKeeperModule::on_initialize(30);
+		assert_eq!(Balances::free_balance(&BOB), 200);
+		assert_eq!(TimeKeeperModule::creditors(ORG, &BOB), None);
+	});
+}
+
+#[test]
+fn it_pays_overtime_past_the_daily_threshold() {
+	new_test_ext().execute_with(|| {
+		setup_roles(ALICE);
+		assert_ok!(TimeKeeperModule::register_account(Origin::signed(ALICE), ORG, BOB, Some(RateSpec::Hourly(RATE))));
+		let policy = PayPolicy { daily_threshold: Some(8 * HOUR), overtime_multiplier: 150, ..Default::default() };
+		assert_noop!(TimeKeeperModule::set_pay_policy(Origin::signed(BOB), ORG, Some(policy.clone())),
+			Error::<Test>::ErrorAdminRoleRequired);
+		assert_ok!(TimeKeeperModule::set_pay_policy(Origin::signed(ALICE), ORG, Some(policy)));
+		// Six hours at the plain rate
+		assert_ok!(TimeKeeperModule::enter_account(Origin::signed(BOB), ORG, None));
+		Timestamp::set_timestamp(6 * HOUR);
+		assert_ok!(TimeKeeperModule::exit_account(Origin::signed(BOB), None));
+		assert_eq!(TimeKeeperModule::creditors(ORG, &BOB), Some(6 * RATE));
+		// Four more hours the same day, the last two are overtime
+		Timestamp::set_timestamp(7 * HOUR);
+		assert_ok!(TimeKeeperModule::enter_account(Origin::signed(BOB), ORG, None));
+		Timestamp::set_timestamp(11 * HOUR);
+		assert_ok!(TimeKeeperModule::exit_account(Origin::signed(BOB), None));
+		let shift = TimeKeeperModule::timesheets(&BOB, 1).unwrap();
+		assert_eq!(shift.credit, 2 * RATE + 3 * RATE);
+		assert_eq!(shift.segments, vec![
+			ShiftSegment { start: 7 * HOUR, end: 9 * HOUR, multiplier: 100, credit: 2 * RATE },
+			ShiftSegment { start: 9 * HOUR, end: 11 * HOUR, multiplier: 150, credit: 3 * RATE },
+		]);
+		// The next day starts at the plain rate again
+		Timestamp::set_timestamp(DAY);
+		assert_ok!(TimeKeeperModule::enter_account(Origin::signed(BOB), ORG, None));
+		Timestamp::set_timestamp(DAY + HOUR);
+		assert_ok!(TimeKeeperModule::exit_account(Origin::signed(BOB), None));
+		assert_eq!(TimeKeeperModule::timesheets(&BOB, 2).unwrap().credit, RATE);
+		assert_eq!(TimeKeeperModule::creditors(ORG, &BOB), Some(12 * RATE));
+	});
+}
+
+#[test]
+fn it_splits_shifts_at_night_and_weekend_boundaries() {
+	new_test_ext().execute_with(|| {
+		setup_roles(ALICE);
+		assert_ok!(TimeKeeperModule::register_account(Origin::signed(ALICE), ORG, BOB, Some(RateSpec::Hourly(RATE))));
+		assert_noop!(TimeKeeperModule::set_pay_policy(Origin::signed(ALICE), ORG, Some(PayPolicy { night: Some((DAY, 0)), ..Default::default() })),
+			Error::<Test>::ErrorInvalidPayPolicy);
+		assert_ok!(TimeKeeperModule::set_pay_policy(Origin::signed(ALICE), ORG, Some(PayPolicy {
+			night: Some((22 * HOUR, 6 * HOUR)),
+			night_multiplier: 120,
+			weekend_multiplier: 200,
+			..Default::default()
+		})));
+		// Friday 2 January 1970 from 8pm to Saturday 4am
+		let friday = DAY;
+		Timestamp::set_timestamp(friday + 20 * HOUR);
+		assert_ok!(TimeKeeperModule::enter_account(Origin::signed(BOB), ORG, None));
+		Timestamp::set_timestamp(friday + 28 * HOUR);
+		assert_ok!(TimeKeeperModule::exit_account(Origin::signed(BOB), None));
+		let shift = TimeKeeperModule::timesheets(&BOB, 0).unwrap();
+		assert_eq!(shift.segments, vec![
+			ShiftSegment { start: friday + 20 * HOUR, end: friday + 22 * HOUR, multiplier: 100, credit: 2 * RATE },
+			ShiftSegment { start: friday + 22 * HOUR, end: friday + 24 * HOUR, multiplier: 120, credit: 24 },
+			// The weekend multiplier is higher than the night one
+			ShiftSegment { start: friday + 24 * HOUR, end: friday + 28 * HOUR, multiplier: 200, credit: 8 * RATE },
+		]);
+		assert_eq!(shift.credit, 2 * RATE + 24 + 8 * RATE);
+		assert_eq!(TimeKeeperModule::creditors(ORG, &BOB), Some(shift.credit));
+	});
+}
+
+#[test]
+fn it_leaves_unpaid_breaks_out_of_the_credit() {
+	new_test_ext().execute_with(|| {
+		setup_roles(ALICE);
+		assert_ok!(TimeKeeperModule::register_account(Origin::signed(ALICE), ORG, BOB, Some(RateSpec::Hourly(RATE))));
+		assert_noop!(TimeKeeperModule::start_break(Origin::signed(BOB), false), Error::<Test>::FailedToStartBreak);
+		assert_ok!(TimeKeeperModule::enter_account(Origin::signed(BOB), ORG, None));
+		assert_noop!(TimeKeeperModule::end_break(Origin::signed(BOB)), Error::<Test>::FailedToEndBreak);
+		// Unpaid lunch break of an hour
+		Timestamp::set_timestamp(2 * HOUR);
+		assert_ok!(TimeKeeperModule::start_break(Origin::signed(BOB), false));
+		assert_eq!(last_event(), Event::timekeeper(RawEvent::BreakStarted(BOB, false)));
+		assert_noop!(TimeKeeperModule::start_break(Origin::signed(BOB), true), Error::<Test>::FailedToStartBreak);
+		Timestamp::set_timestamp(3 * HOUR);
+		assert_ok!(TimeKeeperModule::end_break(Origin::signed(BOB)));
+		assert_eq!(last_event(), Event::timekeeper(RawEvent::BreakEnded(BOB, HOUR)));
+		// Paid coffee break of half an hour
+		Timestamp::set_timestamp(4 * HOUR);
+		assert_ok!(TimeKeeperModule::start_break(Origin::signed(BOB), true));
+		Timestamp::set_timestamp(4 * HOUR + HOUR / 2);
+		assert_ok!(TimeKeeperModule::end_break(Origin::signed(BOB)));
+		Timestamp::set_timestamp(7 * HOUR);
+		assert_ok!(TimeKeeperModule::exit_account(Origin::signed(BOB), None));
+		// Seven hours less the unpaid hour are credited
+		assert_eq!(TimeKeeperModule::creditors(ORG, &BOB), Some(6 * RATE));
+		let shift = TimeKeeperModule::timesheets(&BOB, 0).unwrap();
+		assert_eq!(shift.duration, 6 * HOUR);
+		assert_eq!(shift.breaks, vec![
+			BreakRecord { start: 2 * HOUR, end: 3 * HOUR, paid: false },
+			BreakRecord { start: 4 * HOUR, end: 4 * HOUR + HOUR / 2, paid: true },
+		]);
+		assert_eq!(shift.segments, vec![
+			ShiftSegment { start: 0, end: 2 * HOUR, multiplier: 100, credit: 2 * RATE },
+			ShiftSegment { start: 3 * HOUR, end: 7 * HOUR, multiplier: 100, credit: 4 * RATE },
+		]);
+		assert!(TimeKeeperModule::breaks(&BOB).is_empty());
+	});
+}
+
+#[test]
+fn it_deducts_a_break_from_long_shifts_without_one() {
+	new_test_ext().execute_with(|| {
+		setup_roles(ALICE);
+		assert_ok!(TimeKeeperModule::register_account(Origin::signed(ALICE), ORG, BOB, Some(RateSpec::Hourly(RATE))));
+		assert_ok!(TimeKeeperModule::set_max_shift_duration(Origin::signed(ALICE), ORG, BOB, Some(12 * HOUR)));
+		// Ten hours without a break, the break is deducted from the end
+		assert_ok!(TimeKeeperModule::enter_account(Origin::signed(BOB), ORG, None));
+		Timestamp::set_timestamp(10 * HOUR);
+		assert_ok!(TimeKeeperModule::exit_account(Origin::signed(BOB), None));
+		let shift = TimeKeeperModule::timesheets(&BOB, 0).unwrap();
+		assert_eq!(shift.duration, 10 * HOUR - AutoBreakDuration::get());
+		assert_eq!(shift.breaks, vec![
+			BreakRecord { start: 10 * HOUR - AutoBreakDuration::get(), end: 10 * HOUR, paid: false },
+		]);
+		assert_eq!(TimeKeeperModule::creditors(ORG, &BOB), Some(95));
+		// Ten hours with a short break recorded, exited while on the break
+		Timestamp::set_timestamp(DAY);
+		assert_ok!(TimeKeeperModule::enter_account(Origin::signed(BOB), ORG, None));
+		Timestamp::set_timestamp(DAY + 9 * HOUR + HOUR / 2);
+		assert_ok!(TimeKeeperModule::start_break(Origin::signed(BOB), false));
+		Timestamp::set_timestamp(DAY + 10 * HOUR);
+		assert_ok!(TimeKeeperModule::exit_account(Origin::signed(BOB), None));
+		assert_eq!(TimeKeeperModule::on_break(&BOB), None);
+		let shift = TimeKeeperModule::timesheets(&BOB, 1).unwrap();
+		assert_eq!(shift.breaks, vec![
+			BreakRecord { start: DAY + 9 * HOUR + HOUR / 2, end: DAY + 10 * HOUR, paid: false },
+		]);
+		assert_eq!(TimeKeeperModule::creditors(ORG, &BOB), Some(190));
+	});
+}
+
+#[test]
+fn it_bills_shifts_to_projects_and_warns_on_budgets() {
+	new_test_ext().execute_with(|| {
+		setup_roles(ALICE);
+		assert_ok!(TimeKeeperModule::register_account(Origin::signed(ALICE), ORG, BOB, Some(RateSpec::Hourly(RATE))));
+		assert_noop!(TimeKeeperModule::create_project(Origin::signed(BOB), ORG, None, None), Error::<Test>::ErrorAdminRoleRequired);
+		assert_ok!(TimeKeeperModule::create_project(Origin::signed(ALICE), ORG, Some(10 * HOUR), Some(20 * RATE)));
+		assert_eq!(last_event(), Event::timekeeper(RawEvent::ProjectCreated(ALICE, ORG, 0)));
+		assert_eq!(TimeKeeperModule::project_count(), 1);
+		// BOB has to be allowed to work on the project
+		assert_noop!(TimeKeeperModule::enter_project(Origin::signed(BOB), 1, None), Error::<Test>::ErrorProjectNotFound);
+		assert_noop!(TimeKeeperModule::enter_project(Origin::signed(BOB), 0, None), Error::<Test>::ErrorNotProjectWorker);
+		assert_ok!(TimeKeeperModule::add_project_worker(Origin::signed(ALICE), 0, BOB));
+		// Eight hours use 80% of the time budget
+		assert_ok!(TimeKeeperModule::enter_project(Origin::signed(BOB), 0, None));
+		assert_eq!(TimeKeeperModule::active_project(&BOB), Some(0));
+		Timestamp::set_timestamp(8 * HOUR);
+		assert_ok!(TimeKeeperModule::exit_account(Origin::signed(BOB), None));
+		assert!(System::events().iter().any(|r| r.event == Event::timekeeper(RawEvent::BudgetWarning(0))));
+		assert_eq!(TimeKeeperModule::active_project(&BOB), None);
+		assert_eq!(TimeKeeperModule::timesheets(&BOB, 0).unwrap().project, Some(0));
+		// A shift not billed to the project
+		assert_ok!(TimeKeeperModule::enter_account(Origin::signed(BOB), ORG, None));
+		Timestamp::set_timestamp(9 * HOUR);
+		assert_ok!(TimeKeeperModule::exit_account(Origin::signed(BOB), None));
+		assert_eq!(TimeKeeperModule::timesheets(&BOB, 1).unwrap().project, None);
+		// Two more hours exhaust the time budget
+		System::reset_events();
+		assert_ok!(TimeKeeperModule::enter_project(Origin::signed(BOB), 0, None));
+		Timestamp::set_timestamp(11 * HOUR);
+		assert_ok!(TimeKeeperModule::exit_account(Origin::signed(BOB), None));
+		assert!(System::events().iter().any(|r| r.event == Event::timekeeper(RawEvent::BudgetExhausted(0))));
+		assert!(!System::events().iter().any(|r| r.event == Event::timekeeper(RawEvent::BudgetWarning(0))));
+		assert_eq!(TimeKeeperModule::projects(0), Some(Project {
+			org: ORG,
+			time_budget: Some(10 * HOUR),
+			credit_budget: Some(20 * RATE),
+			time: 10 * HOUR,
+			credit: 10 * RATE,
+		}));
+		// Removed workers can't enter the project
+		assert_ok!(TimeKeeperModule::remove_project_worker(Origin::signed(ALICE), 0, BOB));
+		assert_noop!(TimeKeeperModule::enter_project(Origin::signed(BOB), 0, None), Error::<Test>::ErrorNotProjectWorker);
+	});
+}
+
+#[test]
+fn it_keeps_organizations_separate() {
+	new_test_ext().execute_with(|| {
+		setup_roles(ALICE);
+		// Only global administrators can create organizations
+		assert_noop!(TimeKeeperModule::create_organization(Origin::signed(CHARLIE), CHARLIE), Error::<Test>::ErrorAdminRoleRequired);
+		assert_ok!(TimeKeeperModule::create_organization(Origin::signed(ALICE), CHARLIE));
+		assert_eq!(last_event(), Event::timekeeper(RawEvent::OrganizationCreated(ALICE, 1, CHARLIE)));
+		let registrar = TimeKeeperModule::organizations(1).unwrap().registrar_role;
+		let supervisor = TimeKeeperModule::organizations(1).unwrap().supervisor_role;
+		// Administrators hold the roles below them
+		assert!(AccessModule::has_role(registrar, CHARLIE));
+		assert!(AccessModule::has_role(supervisor, CHARLIE));
+		assert!(!AccessModule::has_role(supervisor, ALICE));
+		assert_ok!(AccessModule::grant_role(Origin::signed(CHARLIE), registrar, CHARLIE, None));
+		// Administrators of one organization have no rights in another
+		assert_noop!(TimeKeeperModule::register_account(Origin::signed(ALICE), 1, BOB, Some(RateSpec::Hourly(RATE))),
+					Error::<Test>::ErrorRegistrarRoleRequired);
+		assert_noop!(TimeKeeperModule::register_account(Origin::signed(ALICE), 2, BOB, Some(RateSpec::Hourly(RATE))),
+					Error::<Test>::ErrorOrganizationNotFound);
+		assert_ok!(TimeKeeperModule::register_account(Origin::signed(ALICE), ORG, BOB, Some(RateSpec::Hourly(RATE))));
+		assert_ok!(TimeKeeperModule::register_account(Origin::signed(CHARLIE), 1, BOB, Some(RateSpec::Hourly(2 * RATE))));
+		// Each organization has its own pot
+		assert_ok!(TimeKeeperModule::deposit(Origin::signed(FUNDER), ORG, 100));
+		assert_ok!(TimeKeeperModule::deposit(Origin::signed(FUNDER), 1, 50));
+		assert_ne!(TimeKeeperModule::account_id(ORG), TimeKeeperModule::account_id(1));
+		assert_eq!(TimeKeeperModule::pot_balance(ORG), 100);
+		assert_eq!(TimeKeeperModule::pot_balance(1), 50);
+		// BOB can only be entered with one organization at a time
+		assert_ok!(TimeKeeperModule::enter_account(Origin::signed(BOB), 1, None));
+		assert_noop!(TimeKeeperModule::enter_account(Origin::signed(BOB), ORG, None), Error::<Test>::FailedToEnter);
+		Timestamp::set_timestamp(HOUR);
+		assert_ok!(TimeKeeperModule::exit_account(Origin::signed(BOB), None));
+		assert_eq!(last_event(), Event::timekeeper(RawEvent::AccountExited(1, BOB)));
+		assert_eq!(TimeKeeperModule::creditors(1, &BOB), Some(2 * RATE));
+		assert_eq!(TimeKeeperModule::creditors(ORG, &BOB), None);
+		assert_eq!(TimeKeeperModule::timesheets(&BOB, 0).unwrap().org, 1);
+		// Credit is paid from the pot of the organization that owes it
+		assert_ok!(TimeKeeperModule::withdraw_all(Origin::signed(BOB), 1));
+		assert_eq!(Balances::free_balance(&BOB), 2 * RATE);
+		assert_eq!(TimeKeeperModule::pot_balance(1), 50 - 2 * RATE);
+		assert_eq!(TimeKeeperModule::pot_balance(ORG), 100);
+	});
+}
+
+#[test]
+fn it_holds_credit_until_shifts_are_approved() {
+	new_test_ext().execute_with(|| {
+		setup_roles(ALICE);
+		assert_ok!(TimeKeeperModule::register_account(Origin::signed(ALICE), ORG, BOB, Some(RateSpec::Hourly(RATE))));
+		let approver = TimeKeeperModule::organizations(ORG).unwrap().approver_role;
+		assert_ok!(AccessModule::grant_role(Origin::signed(ALICE), approver, CHARLIE, None));
+		assert_noop!(TimeKeeperModule::set_approval_window(Origin::signed(CHARLIE), ORG, Some(5)), Error::<Test>::ErrorAdminRoleRequired);
+		// Shifts are approved when a block starts, so the window is at least a block
+		assert_noop!(TimeKeeperModule::set_approval_window(Origin::signed(ALICE), ORG, Some(0)), Error::<Test>::ErrorInvalidApprovalWindow);
+		assert_ok!(TimeKeeperModule::set_approval_window(Origin::signed(ALICE), ORG, Some(5)));
+		assert_eq!(last_event(), Event::timekeeper(RawEvent::ApprovalWindowSet(ALICE, ORG, Some(5))));
+
+		// The shift is pending and its credit isn't withdrawable
+		assert_ok!(TimeKeeperModule::enter_account(Origin::signed(BOB), ORG, None));
+		Timestamp::set_timestamp(2 * HOUR);
+		assert_ok!(TimeKeeperModule::exit_account(Origin::signed(BOB), None));
+		assert!(System::events().iter().any(|r| r.event == Event::timekeeper(RawEvent::ShiftPending(BOB, 0, 6))));
+		assert_eq!(TimeKeeperModule::timesheets(&BOB, 0).unwrap().status, ShiftStatus::Pending);
+		assert_eq!(TimeKeeperModule::creditors(ORG, &BOB), None);
+		assert_noop!(TimeKeeperModule::withdraw_all(Origin::signed(BOB), ORG), Error::<Test>::FailedCredit);
+		// Nor can the administrator reclaim the funds it will be paid from
+		assert_ok!(TimeKeeperModule::deposit(Origin::signed(FUNDER), ORG, 100));
+		assert_eq!(TimeKeeperModule::pending_credit(ORG), 2 * RATE);
+		assert_eq!(TimeKeeperModule::surplus(ORG), 100 - 2 * RATE);
+		assert_noop!(TimeKeeperModule::reclaim_surplus(Origin::signed(ALICE), ORG, 100 - 2 * RATE + 1), Error::<Test>::ErrorInsufficientSurplus);
+
+		// Only approvers other than the account holder can review
+		assert_noop!(TimeKeeperModule::approve_shift(Origin::signed(BOB), BOB, 0), Error::<Test>::ErrorApproverRoleRequired);
+		assert_noop!(TimeKeeperModule::approve_shift(Origin::signed(ALICE), BOB, 0), Error::<Test>::ErrorApproverRoleRequired);
+		assert_noop!(TimeKeeperModule::approve_shift(Origin::signed(CHARLIE), BOB, 1), Error::<Test>::ErrorShiftNotFound);
+
+		// The approver credits one of the two hours
+		assert_noop!(TimeKeeperModule::edit_shift(Origin::signed(CHARLIE), BOB, 0, 3 * HOUR), Error::<Test>::ErrorInvalidShiftDuration);
+		assert_ok!(TimeKeeperModule::edit_shift(Origin::signed(CHARLIE), BOB, 0, HOUR));
+		assert_eq!(last_event(), Event::timekeeper(RawEvent::ShiftEdited(CHARLIE, BOB, 0, HOUR)));
+		assert_eq!(TimeKeeperModule::timesheets(&BOB, 0).unwrap().credit, RATE);
+		assert_eq!(TimeKeeperModule::creditors(ORG, &BOB), None);
+		assert_eq!(TimeKeeperModule::pending_credit(ORG), RATE);
+		assert_eq!(TimeKeeperModule::timesheets(&BOB, 0).unwrap().segments,
+			vec![ShiftSegment { start: 0, end: 2 * HOUR, multiplier: 100, credit: RATE }]);
+		assert_ok!(TimeKeeperModule::approve_shift(Origin::signed(CHARLIE), BOB, 0));
+		assert_eq!(last_event(), Event::timekeeper(RawEvent::ShiftApproved(CHARLIE, BOB, 0)));
+		assert_eq!(TimeKeeperModule::timesheets(&BOB, 0).unwrap().status, ShiftStatus::Approved);
+		assert_eq!(TimeKeeperModule::creditors(ORG, &BOB), Some(RATE));
+		assert_eq!(TimeKeeperModule::pending_credit(ORG), 0);
+		assert_eq!(TimeKeeperModule::surplus(ORG), 100 - RATE);
+		assert_noop!(TimeKeeperModule::approve_shift(Origin::signed(CHARLIE), BOB, 0), Error::<Test>::ErrorShiftNotPending);
+
+		// A rejected shift is never credited
+		assert_ok!(TimeKeeperModule::enter_account(Origin::signed(BOB), ORG, None));
+		Timestamp::set_timestamp(4 * HOUR);
+		assert_ok!(TimeKeeperModule::exit_account(Origin::signed(BOB), None));
+		assert_ok!(TimeKeeperModule::reject_shift(Origin::signed(CHARLIE), BOB, 1, 3));
+		assert_eq!(last_event(), Event::timekeeper(RawEvent::ShiftRejected(CHARLIE, BOB, 1, 3)));
+		assert_eq!(TimeKeeperModule::pending_credit(ORG), 0);
+		assert_eq!(TimeKeeperModule::timesheets(&BOB, 1).unwrap().status, ShiftStatus::Rejected(3));
+		assert_noop!(TimeKeeperModule::edit_shift(Origin::signed(CHARLIE), BOB, 1, HOUR), Error::<Test>::ErrorShiftNotPending);
+
+		// A shift nobody reviews is approved once the window has passed
+		assert_ok!(TimeKeeperModule::enter_account(Origin::signed(BOB), ORG, None));
+		Timestamp::set_timestamp(6 * HOUR);
+		assert_ok!(TimeKeeperModule::exit_account(Origin::signed(BOB), None));
+		TimeKeeperModule::on_initialize(5);
+		assert_eq!(TimeKeeperModule::timesheets(&BOB, 2).unwrap().status, ShiftStatus::Pending);
+		TimeKeeperModule::on_initialize(6);
+		assert!(System::events().iter().any(|r| r.event == Event::timekeeper(RawEvent::ShiftAutoApproved(BOB, 2))));
+		assert!(!System::events().iter().any(|r| r.event == Event::timekeeper(RawEvent::ShiftAutoApproved(BOB, 1))));
+		assert_eq!(TimeKeeperModule::timesheets(&BOB, 2).unwrap().status, ShiftStatus::Approved);
+		assert_eq!(TimeKeeperModule::timesheets(&BOB, 1).unwrap().status, ShiftStatus::Rejected(3));
+		assert_eq!(TimeKeeperModule::creditors(ORG, &BOB), Some(3 * RATE));
+		assert!(TimeKeeperModule::pending_approvals(6).is_empty());
+	});
+}
+
+#[test]
+fn it_corrects_missed_punches() {
+	new_test_ext().execute_with(|| {
+		setup_roles(ALICE);
+		assert_ok!(TimeKeeperModule::register_account(Origin::signed(ALICE), ORG, BOB, Some(RateSpec::Hourly(RATE))));
+		let justification = H256::repeat_byte(1);
+
+		// BOB forgets to exit and asks for the open shift to end after two hours
+		assert_ok!(TimeKeeperModule::enter_account(Origin::signed(BOB), ORG, None));
+		Timestamp::set_timestamp(3 * HOUR);
+		assert_noop!(TimeKeeperModule::request_correction(Origin::signed(BOB), 0, 0, 4 * HOUR, justification),
+					Error::<Test>::ErrorInvalidCorrection);
+		assert_noop!(TimeKeeperModule::request_correction(Origin::signed(BOB), 1, 0, 2 * HOUR, justification),
+					Error::<Test>::ErrorShiftNotFound);
+		assert_ok!(TimeKeeperModule::request_correction(Origin::signed(BOB), 0, 0, 2 * HOUR, justification));
+		assert_eq!(last_event(), Event::timekeeper(RawEvent::CorrectionRequested(BOB, 0, 0, 2 * HOUR, justification)));
+		assert_noop!(TimeKeeperModule::request_correction(Origin::signed(BOB), 0, 0, HOUR, justification),
+					Error::<Test>::ErrorCorrectionExists);
+		assert_noop!(TimeKeeperModule::approve_correction(Origin::signed(BOB), BOB, 0), Error::<Test>::ErrorAdminRoleRequired);
+		assert_ok!(TimeKeeperModule::approve_correction(Origin::signed(ALICE), BOB, 0));
+		assert_eq!(last_event(), Event::timekeeper(RawEvent::CorrectionApproved(ALICE, BOB, 0)));
+		assert_eq!(TimeKeeperModule::entered(&BOB), None);
+		assert_eq!(TimeKeeperModule::timesheets(&BOB, 0).unwrap().end, 2 * HOUR);
+		assert_eq!(TimeKeeperModule::creditors(ORG, &BOB), Some(2 * RATE));
+		assert_eq!(TimeKeeperModule::corrections(&BOB, (0, 0)), Some(CorrectionRequest {
+			start: 0,
+			end: 2 * HOUR,
+			justification,
+			original: (0, None),
+			status: CorrectionStatus::Approved,
+		}));
+
+		// A recorded shift is recalculated and the credit taken back
+		assert_ok!(TimeKeeperModule::enter_account(Origin::signed(BOB), ORG, None));
+		Timestamp::set_timestamp(9 * HOUR);
+		assert_ok!(TimeKeeperModule::exit_account(Origin::signed(BOB), None));
+		assert_eq!(TimeKeeperModule::creditors(ORG, &BOB), Some(8 * RATE));
+		assert_ok!(TimeKeeperModule::request_correction(Origin::signed(BOB), 1, 3 * HOUR, 5 * HOUR, justification));
+		assert_ok!(TimeKeeperModule::approve_correction(Origin::signed(ALICE), BOB, 1));
+		let shift = TimeKeeperModule::timesheets(&BOB, 1).unwrap();
+		assert_eq!((shift.start, shift.end, shift.duration, shift.credit), (3 * HOUR, 5 * HOUR, 2 * HOUR, 2 * RATE));
+		assert_eq!(TimeKeeperModule::creditors(ORG, &BOB), Some(4 * RATE));
+		assert_eq!(TimeKeeperModule::total_outstanding(ORG), 4 * RATE);
+		assert_eq!(TimeKeeperModule::corrections(&BOB, (1, 0)).unwrap().original, (3 * HOUR, Some(9 * HOUR)));
+
+		// A denied correction leaves the shift unchanged
+		assert_ok!(TimeKeeperModule::request_correction(Origin::signed(BOB), 1, 3 * HOUR, 8 * HOUR, justification));
+		assert_ok!(TimeKeeperModule::deny_correction(Origin::signed(ALICE), BOB, 1));
+		assert_eq!(last_event(), Event::timekeeper(RawEvent::CorrectionDenied(ALICE, BOB, 1)));
+		assert_eq!(TimeKeeperModule::corrections(&BOB, (1, 1)).unwrap().status, CorrectionStatus::Denied);
+		assert_noop!(TimeKeeperModule::approve_correction(Origin::signed(ALICE), BOB, 1), Error::<Test>::ErrorCorrectionNotPending);
+		assert_eq!(TimeKeeperModule::timesheets(&BOB, 1).unwrap().end, 5 * HOUR);
+		assert_eq!(TimeKeeperModule::creditors(ORG, &BOB), Some(4 * RATE));
+
+		// Correcting the shift again keeps the earlier corrections and their original times
+		assert_ok!(TimeKeeperModule::request_correction(Origin::signed(BOB), 1, 3 * HOUR, 6 * HOUR, justification));
+		assert_ok!(TimeKeeperModule::approve_correction(Origin::signed(ALICE), BOB, 1));
+		assert_eq!(TimeKeeperModule::correction_count(&BOB, 1), 3);
+		let first = TimeKeeperModule::corrections(&BOB, (1, 0)).unwrap();
+		assert_eq!((first.end, first.original, first.status), (5 * HOUR, (3 * HOUR, Some(9 * HOUR)), CorrectionStatus::Approved));
+		let second = TimeKeeperModule::corrections(&BOB, (1, 2)).unwrap();
+		assert_eq!((second.end, second.original, second.status), (6 * HOUR, (3 * HOUR, Some(5 * HOUR)), CorrectionStatus::Approved));
+		assert_eq!(TimeKeeperModule::creditors(ORG, &BOB), Some(5 * RATE));
+	});
+}
+
+fn signed_punch(worker: u64, kind: PunchKind, nonce: u32) -> crate::Call<Test> {
+	let punch = Punch { worker, device: DEVICE, kind, nonce };
+	let payload = codec::Encode::encode(&punch);
+	crate::Call::punch(punch, TestSignature(worker, payload.clone()), TestSignature(DEVICE, payload))
+}
+
+fn submit_punch(call: crate::Call<Test>) -> frame_support::dispatch::DispatchResult {
+	match call {
+		crate::Call::punch(punch, worker_signature, device_signature) =>
+			TimeKeeperModule::punch(Origin::none(), punch, worker_signature, device_signature),
+		_ => unreachable!(),
+	}
+}
+
+#[test]
+fn it_punches_on_registered_devices() {
+	new_test_ext().execute_with(|| {
+		setup_roles(ALICE);
+		assert_ok!(TimeKeeperModule::register_account(Origin::signed(ALICE), ORG, BOB, Some(RateSpec::Hourly(RATE))));
+		assert_ok!(TimeKeeperModule::register_account(Origin::signed(ALICE), ORG, CHARLIE, Some(RateSpec::Hourly(RATE))));
+		assert_noop!(TimeKeeperModule::register_device(Origin::signed(BOB), ORG, SITE, UintAuthorityId(DEVICE)),
+					Error::<Test>::ErrorAdminRoleRequired);
+		assert_ok!(TimeKeeperModule::register_device(Origin::signed(ALICE), ORG, SITE, UintAuthorityId(DEVICE)));
+		assert_eq!(last_event(), Event::timekeeper(RawEvent::DeviceRegistered(ALICE, ORG, SITE, DEVICE)));
+		assert_eq!(TimeKeeperModule::devices(DEVICE), Some(Device { org: ORG, site: SITE, active: true }));
+		assert_noop!(TimeKeeperModule::register_device(Origin::signed(ALICE), ORG, SITE, UintAuthorityId(DEVICE)),
+					Error::<Test>::ErrorDeviceExists);
+		assert_ok!(TimeKeeperModule::set_site_worker(Origin::signed(ALICE), ORG, SITE, BOB, true));
+
+		// Only workers allowed at the site of the device can punch
+		assert_eq!(TimeKeeperModule::validate_unsigned(TransactionSource::External, &signed_punch(CHARLIE, PunchKind::Enter, 0)),
+					InvalidTransaction::Call.into());
+		// Both the worker and the device have to sign the punch
+		let punch = Punch { worker: BOB, device: DEVICE, kind: PunchKind::Enter, nonce: 0 };
+		let payload = codec::Encode::encode(&punch);
+		let forged = crate::Call::punch(punch, TestSignature(BOB, payload.clone()), TestSignature(BOB, payload));
+		assert_eq!(TimeKeeperModule::validate_unsigned(TransactionSource::External, &forged), InvalidTransaction::BadProof.into());
+		assert_noop!(submit_punch(forged), Error::<Test>::ErrorInvalidPunchSignature);
+
+		let enter = signed_punch(BOB, PunchKind::Enter, 0);
+		assert!(TimeKeeperModule::validate_unsigned(TransactionSource::External, &enter).is_ok());
+		assert_noop!(TimeKeeperModule::punch(Origin::signed(BOB), Punch { worker: BOB, device: DEVICE, kind: PunchKind::Enter, nonce: 0 },
+					TestSignature(BOB, vec![]), TestSignature(DEVICE, vec![])), frame_support::error::BadOrigin);
+		assert_ok!(submit_punch(enter.clone()));
+		assert_eq!(last_event(), Event::timekeeper(RawEvent::Punched(DEVICE, BOB, PunchKind::Enter)));
+		assert!(TimeKeeperModule::entered(&BOB).is_some());
+		assert_eq!(TimeKeeperModule::punch_nonce(&BOB), 1);
+		// A punch can't be replayed
+		assert_eq!(TimeKeeperModule::validate_unsigned(TransactionSource::External, &enter), InvalidTransaction::Stale.into());
+
+		Timestamp::set_timestamp(2 * HOUR);
+		assert_ok!(submit_punch(signed_punch(BOB, PunchKind::Exit, 1)));
+		assert_eq!(TimeKeeperModule::entered(&BOB), None);
+		assert_eq!(TimeKeeperModule::creditors(ORG, &BOB), Some(2 * RATE));
+
+		// Punches which can't be applied don't enter the pool, and use up their nonce if included
+		let exit = signed_punch(BOB, PunchKind::Exit, 2);
+		assert_eq!(TimeKeeperModule::validate_unsigned(TransactionSource::External, &exit), InvalidTransaction::Call.into());
+		assert_err!(submit_punch(exit.clone()), Error::<Test>::FailedToExit);
+		assert_eq!(TimeKeeperModule::punch_nonce(&BOB), 3);
+		assert_eq!(TimeKeeperModule::validate_unsigned(TransactionSource::External, &exit), InvalidTransaction::Stale.into());
+
+		// Punches on an inactive device are refused
+		assert_ok!(TimeKeeperModule::set_device_active(Origin::signed(ALICE), DEVICE, false));
+		assert_eq!(last_event(), Event::timekeeper(RawEvent::DeviceStatusSet(ALICE, DEVICE, false)));
+		assert_noop!(submit_punch(signed_punch(BOB, PunchKind::Enter, 3)), Error::<Test>::ErrorDeviceInactive);
+		assert_ok!(TimeKeeperModule::set_device_active(Origin::signed(ALICE), DEVICE, true));
+		assert_ok!(TimeKeeperModule::set_site_worker(Origin::signed(ALICE), ORG, SITE, BOB, false));
+		assert_noop!(submit_punch(signed_punch(BOB, PunchKind::Enter, 3)), Error::<Test>::ErrorNotSiteWorker);
+	});
+}
+
+fn run_challenge_block(n: u64) {
+	System::set_block_number(n);
+	TimeKeeperModule::on_initialize(n);
+}
+
+#[test]
+fn it_requires_recent_challenge_codes() {
+	new_test_ext().execute_with(|| {
+		setup_roles(ALICE);
+		assert_ok!(TimeKeeperModule::register_account(Origin::signed(ALICE), ORG, BOB, Some(RateSpec::Hourly(RATE))));
+		assert_ok!(TimeKeeperModule::set_site_worker(Origin::signed(ALICE), ORG, SITE, BOB, true));
+		assert_noop!(TimeKeeperModule::set_challenge_age(Origin::signed(ALICE), ORG, Some(MaxChallengeAge::get() + 1)),
+					Error::<Test>::ErrorInvalidChallengeAge);
+		assert_ok!(TimeKeeperModule::set_challenge_age(Origin::signed(ALICE), ORG, Some(2)));
+		assert_eq!(last_event(), Event::timekeeper(RawEvent::ChallengeAgeSet(ALICE, ORG, Some(2))));
+		run_challenge_block(2);
+		let code = TimeKeeperModule::challenge_code(ORG, SITE, 2).unwrap();
+
+		// Entering without the code or with a wrong one is counted
+		assert_err!(TimeKeeperModule::enter_account(Origin::signed(BOB), ORG, None), Error::<Test>::ErrorChallengeFailed);
+		assert_eq!(last_event(), Event::timekeeper(RawEvent::ChallengeFailed(ORG, BOB)));
+		let wrong = Challenge { site: SITE, block: 2, code: (code + 1) % 1_000_000 };
+		assert_err!(TimeKeeperModule::enter_account(Origin::signed(BOB), ORG, Some(wrong)), Error::<Test>::ErrorChallengeFailed);
+		let other_site = Challenge { site: SITE + 1, block: 2, code: TimeKeeperModule::challenge_code(ORG, SITE + 1, 2).unwrap() };
+		assert_err!(TimeKeeperModule::enter_account(Origin::signed(BOB), ORG, Some(other_site)), Error::<Test>::ErrorChallengeFailed);
+		assert_eq!(TimeKeeperModule::failed_challenges(ORG, &BOB), 3);
+		assert_eq!(TimeKeeperModule::entered(&BOB), None);
+		assert_ok!(TimeKeeperModule::enter_account(Origin::signed(BOB), ORG, Some(Challenge { site: SITE, block: 2, code })));
+
+		// The code rotates each block and is too old after two blocks
+		run_challenge_block(3);
+		run_challenge_block(4);
+		run_challenge_block(5);
+		assert_err!(TimeKeeperModule::exit_account(Origin::signed(BOB), Some(Challenge { site: SITE, block: 2, code })),
+					Error::<Test>::ErrorChallengeFailed);
+		assert_eq!(TimeKeeperModule::failed_challenges(ORG, &BOB), 4);
+		let recent = TimeKeeperModule::challenge_code(ORG, SITE, 4).unwrap();
+		assert_ok!(TimeKeeperModule::exit_account(Origin::signed(BOB), Some(Challenge { site: SITE, block: 4, code: recent })));
+		assert_eq!(TimeKeeperModule::entered(&BOB), None);
+
+		// Seeds are kept only as long as codes can be accepted
+		run_challenge_block(6);
+		assert_eq!(TimeKeeperModule::challenge_code(ORG, SITE, 2), None);
+		assert!(TimeKeeperModule::challenge_code(ORG, SITE, 3).is_some());
+	});
+}
+
+fn offline_punch(worker: u64, kind: PunchKind, nonce: u32, time: u64) -> (OfflinePunch<u64, u64>, TestSignature, TestSignature) {
+	let punch = OfflinePunch { worker, device: DEVICE, kind, nonce, time };
+	let payload = codec::Encode::encode(&punch);
+	(punch, TestSignature(worker, payload.clone()), TestSignature(DEVICE, payload))
+}
+
+fn was_rejected(nonce: u32, error: Error<Test>) -> bool {
+	System::events().iter().any(|r| r.event == Event::timekeeper(RawEvent::OfflinePunchRejected(BOB, nonce, error.into())))
+}
+
+#[test]
+fn it_applies_offline_punches_at_their_recorded_times() {
+	new_test_ext().execute_with(|| {
+		setup_roles(ALICE);
+		assert_ok!(TimeKeeperModule::register_account(Origin::signed(ALICE), ORG, BOB, Some(RateSpec::Hourly(RATE))));
+		assert_ok!(TimeKeeperModule::register_device(Origin::signed(ALICE), ORG, SITE, UintAuthorityId(DEVICE)));
+		assert_ok!(TimeKeeperModule::set_site_worker(Origin::signed(ALICE), ORG, SITE, BOB, true));
+		Timestamp::set_timestamp(10 * HOUR);
+
+		assert_noop!(TimeKeeperModule::submit_offline_punches(Origin::signed(CHARLIE), vec![
+			offline_punch(BOB, PunchKind::Enter, 0, 5 * HOUR); MaxOfflinePunches::get() as usize + 1
+		]), Error::<Test>::ErrorTooManyPunches);
+
+		// Anyone can relay the punches, an enter before the previous exit is refused
+		assert_ok!(TimeKeeperModule::submit_offline_punches(Origin::signed(CHARLIE), vec![
+			offline_punch(BOB, PunchKind::Enter, 0, 5 * HOUR),
+			offline_punch(BOB, PunchKind::Exit, 1, 7 * HOUR),
+			offline_punch(BOB, PunchKind::Enter, 2, 6 * HOUR),
+			offline_punch(BOB, PunchKind::Enter, 3, 8 * HOUR),
+		]));
+		assert!(was_rejected(2, Error::<Test>::ErrorPunchOutOfOrder));
+		let shift = TimeKeeperModule::timesheets(&BOB, 0).unwrap();
+		assert_eq!((shift.start, shift.end), (5 * HOUR, 7 * HOUR));
+		assert_eq!(TimeKeeperModule::creditors(ORG, &BOB), Some(2 * RATE));
+		assert_eq!(TimeKeeperModule::entered(&BOB), Some((ORG, 8 * HOUR)));
+		assert_eq!(TimeKeeperModule::punch_nonce(&BOB), 4);
+
+		// Punches from the future, too old or not signed by the device are refused
+		System::reset_events();
+		let (punch, worker_signature, _) = offline_punch(BOB, PunchKind::Exit, 7, 9 * HOUR);
+		assert_ok!(TimeKeeperModule::submit_offline_punches(Origin::signed(CHARLIE), vec![
+			offline_punch(BOB, PunchKind::Exit, 4, 11 * HOUR),
+			offline_punch(BOB, PunchKind::Exit, 5, 9 * HOUR),
+			offline_punch(BOB, PunchKind::Enter, 6, HOUR),
+			(punch, worker_signature.clone(), worker_signature),
+		]));
+		assert!(was_rejected(4, Error::<Test>::ErrorPunchInFuture));
+		assert!(was_rejected(6, Error::<Test>::ErrorPunchTooOld));
+		assert!(was_rejected(7, Error::<Test>::ErrorInvalidPunchSignature));
+		assert_eq!(TimeKeeperModule::timesheets(&BOB, 1).unwrap().end, 9 * HOUR);
+		assert_eq!(TimeKeeperModule::creditors(ORG, &BOB), Some(3 * RATE));
+		assert_eq!(TimeKeeperModule::entered(&BOB), None);
+		assert_eq!(TimeKeeperModule::punch_nonce(&BOB), 7);
+
+		// Exits after the maximum shift duration are capped to it
+		Timestamp::set_timestamp(20 * HOUR);
+		assert_ok!(TimeKeeperModule::enter_account(Origin::signed(BOB), ORG, None));
+		Timestamp::set_timestamp(30 * HOUR);
+		assert_ok!(TimeKeeperModule::submit_offline_punches(Origin::signed(CHARLIE), vec![
+			offline_punch(BOB, PunchKind::Exit, 7, 29 * HOUR),
+		]));
+		assert!(System::events().iter().any(|r| r.event == Event::timekeeper(RawEvent::AutoExited(ORG, BOB, MaxShiftDuration::get()))));
+		let shift = TimeKeeperModule::timesheets(&BOB, 2).unwrap();
+		assert_eq!((shift.end, shift.auto_exited), (28 * HOUR, true));
+		assert_eq!(TimeKeeperModule::creditors(ORG, &BOB), Some(11 * RATE));
+	});
+}
+
+#[test]
+fn it_lets_supervisors_enter_and_exit_assigned_workers() {
+	new_test_ext().execute_with(|| {
+		setup_roles(ALICE);
+		assert_ok!(TimeKeeperModule::register_account(Origin::signed(ALICE), ORG, BOB, Some(RateSpec::Hourly(RATE))));
+		assert_ok!(TimeKeeperModule::register_account(Origin::signed(ALICE), ORG, CHARLIE, Some(RateSpec::Hourly(RATE))));
+		let supervisor = TimeKeeperModule::organizations(ORG).unwrap().supervisor_role;
+		assert_ok!(AccessModule::grant_role(Origin::signed(ALICE), supervisor, DAVE, None));
+		assert_ok!(TimeKeeperModule::assign_worker(Origin::signed(ALICE), ORG, DAVE, BOB, true));
+		assert_eq!(last_event(), Event::timekeeper(RawEvent::WorkerAssigned(ALICE, ORG, DAVE, BOB, true)));
+
+		// Only supervisors can enter workers, and only those assigned to them
+		assert_noop!(TimeKeeperModule::enter_for(Origin::signed(CHARLIE), ORG, BOB), Error::<Test>::ErrorSupervisorRoleRequired);
+		assert_noop!(TimeKeeperModule::enter_for(Origin::signed(DAVE), ORG, CHARLIE), Error::<Test>::ErrorWorkerNotAssigned);
+		assert_ok!(TimeKeeperModule::enter_for(Origin::signed(DAVE), ORG, BOB));
+		assert_eq!(last_event(), Event::timekeeper(RawEvent::EnteredFor(DAVE, ORG, BOB)));
+		assert_eq!(TimeKeeperModule::entered_by(&BOB), Some(DAVE));
+		Timestamp::set_timestamp(2 * HOUR);
+		assert_ok!(TimeKeeperModule::exit_for(Origin::signed(DAVE), BOB));
+		assert_eq!(last_event(), Event::timekeeper(RawEvent::ExitedFor(DAVE, ORG, BOB)));
+		let shift = TimeKeeperModule::timesheets(&BOB, 0).unwrap();
+		assert_eq!((shift.entered_by, shift.exited_by), (Some(DAVE), Some(DAVE)));
+		assert_eq!(TimeKeeperModule::entered_by(&BOB), None);
+		// Shifts recorded by a supervisor wait for review, so the worker can dispute them
+		assert_eq!(shift.status, ShiftStatus::Pending);
+		assert_eq!(TimeKeeperModule::creditors(ORG, &BOB), None);
+
+		// Workers can only dispute shifts recorded by a supervisor
+		let reason = H256::repeat_byte(2);
+		assert_ok!(TimeKeeperModule::enter_account(Origin::signed(BOB), ORG, None));
+		assert_ok!(TimeKeeperModule::exit_account(Origin::signed(BOB), None));
+		assert_noop!(TimeKeeperModule::dispute_shift(Origin::signed(BOB), 1, reason), Error::<Test>::ErrorNotRecordedBySupervisor);
+		assert_ok!(TimeKeeperModule::dispute_shift(Origin::signed(BOB), 0, reason));
+		assert_eq!(last_event(), Event::timekeeper(RawEvent::ShiftDisputed(BOB, 0, reason)));
+		assert_eq!(TimeKeeperModule::disputes(&BOB, 0), Some(reason));
+		assert_noop!(TimeKeeperModule::dispute_shift(Origin::signed(BOB), 0, reason), Error::<Test>::ErrorShiftDisputed);
+
+		// Approvers and administrators can dismiss the dispute, the shift is then approved
+		assert_noop!(TimeKeeperModule::dismiss_dispute(Origin::signed(CHARLIE), BOB, 0), Error::<Test>::ErrorApproverRoleRequired);
+		assert_ok!(TimeKeeperModule::dismiss_dispute(Origin::signed(ALICE), BOB, 0));
+		assert!(System::events().iter().any(|r| r.event == Event::timekeeper(RawEvent::DisputeDismissed(ALICE, BOB, 0))));
+		assert_eq!(TimeKeeperModule::disputes(&BOB, 0), None);
+		assert_eq!(TimeKeeperModule::timesheets(&BOB, 0).unwrap().status, ShiftStatus::Approved);
+		assert_eq!(TimeKeeperModule::creditors(ORG, &BOB), Some(2 * RATE));
+		// Approved shifts can't be disputed
+		assert_noop!(TimeKeeperModule::dispute_shift(Origin::signed(BOB), 0, reason), Error::<Test>::ErrorShiftNotPending);
+
+		// With an approval window a disputed shift isn't approved automatically
+		assert_ok!(TimeKeeperModule::set_approval_window(Origin::signed(ALICE), ORG, Some(2)));
+		assert_ok!(TimeKeeperModule::enter_for(Origin::signed(DAVE), ORG, BOB));
+		Timestamp::set_timestamp(3 * HOUR);
+		assert_ok!(TimeKeeperModule::exit_for(Origin::signed(DAVE), BOB));
+		assert_noop!(TimeKeeperModule::dismiss_dispute(Origin::signed(ALICE), BOB, 2), Error::<Test>::ErrorShiftNotDisputed);
+		assert_ok!(TimeKeeperModule::dispute_shift(Origin::signed(BOB), 2, reason));
+		TimeKeeperModule::on_initialize(3);
+		assert_eq!(TimeKeeperModule::timesheets(&BOB, 2).unwrap().status, ShiftStatus::Pending);
+		assert_eq!(TimeKeeperModule::creditors(ORG, &BOB), Some(2 * RATE));
+		// Once dismissed it waits for a new window
+		assert_ok!(TimeKeeperModule::dismiss_dispute(Origin::signed(ALICE), BOB, 2));
+		assert_eq!(last_event(), Event::timekeeper(RawEvent::ShiftPending(BOB, 2, 3)));
+		TimeKeeperModule::on_initialize(3);
+		assert_eq!(TimeKeeperModule::timesheets(&BOB, 2).unwrap().status, ShiftStatus::Approved);
+		assert_eq!(TimeKeeperModule::creditors(ORG, &BOB), Some(3 * RATE));
+
+		// Workers removed from a supervisor can't be exited by it
+		assert_ok!(TimeKeeperModule::enter_for(Origin::signed(DAVE), ORG, BOB));
+		assert_ok!(TimeKeeperModule::assign_worker(Origin::signed(ALICE), ORG, DAVE, BOB, false));
+		assert_noop!(TimeKeeperModule::exit_for(Origin::signed(DAVE), BOB), Error::<Test>::ErrorWorkerNotAssigned);
+	});
+}
+
+#[test]
+fn it_migrates_the_rates_of_the_first_release() {
+	new_test_ext().execute_with(|| {
+		// Store a plain hourly rate as the first release did
+		StorageVersion::put(Releases::V1);
+		put_storage_value(b"Timekeeper", b"RegistrarRole", &[], H256::repeat_byte(1));
+		put_storage_value(b"Timekeeper", b"Rates", &Blake2_128Concat::hash(&BOB.encode()), RATE);
+
+		TimeKeeperModule::on_runtime_upgrade();
+		assert_eq!(TimeKeeperModule::storage_version(), Releases::V2);
+		assert_eq!(TimeKeeperModule::rates(ORG, &BOB), Some(RateSpec::Hourly(RATE)));
+		// Upgrading again leaves the storage alone
+		assert_eq!(TimeKeeperModule::on_runtime_upgrade(), 0);
+		assert_eq!(TimeKeeperModule::rates(ORG, &BOB), Some(RateSpec::Hourly(RATE)));
+	});
+}
+
+#[test]
+fn it_migrates_the_first_release_to_an_organization() {
+	new_test_ext().execute_with(|| {
+		// Set up the roles, credit and pot as the first release did
+		StorageVersion::put(Releases::V1);
+		let registrar = H256::repeat_byte(1);
+		put_storage_value(b"Timekeeper", b"RegistrarRole", &[], registrar);
+		assert_ok!(AccessModule::set_admin_for_role(registrar, TimeKeeperModule::admin_role()));
+		assert_ok!(TimeKeeperModule::setup(Origin::root(), ALICE));
+		assert_ok!(AccessModule::add_account_to_role(registrar, CHARLIE));
+		let key = Blake2_128Concat::hash(&BOB.encode());
+		put_storage_value(b"Timekeeper", b"Rates", &key, RATE);
+		put_storage_value(b"Timekeeper", b"Creditors", &key, 2 * RATE);
+		put_storage_value(b"Timekeeper", b"Entered", &key, HOUR);
+		let legacy_pot = PALLET_ID.into_account();
+		assert_ok!(Balances::transfer(Origin::signed(FUNDER), legacy_pot, 100));
+
+		TimeKeeperModule::on_runtime_upgrade();
+		assert_eq!(TimeKeeperModule::organization_count(), 1);
+		let organization = TimeKeeperModule::organizations(ORG).unwrap();
+		assert_eq!(organization.registrar_role, registrar);
+		assert!(get_storage_value::<H256>(b"Timekeeper", b"RegistrarRole", &[]).is_none());
+		// Timekeeper administrators administer the organization and registrars keep registering
+		assert!(TimeKeeperModule::is_admin(ORG, &ALICE));
+		assert_ok!(TimeKeeperModule::register_account(Origin::signed(CHARLIE), ORG, DAVE, Some(RateSpec::Hourly(RATE))));
+		assert_ok!(AccessModule::grant_role(Origin::signed(ALICE), organization.approver_role, DAVE, None));
+		// The credit, entered accounts and pot belong to the organization
+		assert_eq!(TimeKeeperModule::creditors(ORG, &BOB), Some(2 * RATE));
+		assert_eq!(TimeKeeperModule::total_outstanding(ORG), 2 * RATE);
+		assert_eq!(TimeKeeperModule::entered(&BOB), Some((ORG, HOUR)));
+		assert_eq!(Balances::free_balance(&legacy_pot), 0);
+		assert_eq!(TimeKeeperModule::pot_balance(ORG), 100);
+		// The pot covers the credit, so it isn't reported as underfunded
+		assert!(System::events().iter().all(|record| match record.event {
+			Event::timekeeper(RawEvent::Underfunded(..)) => false,
+			_ => true,
+		}));
+		// BOB can exit the shift entered before the upgrade
+		Timestamp::set_timestamp(2 * HOUR);
+		assert_ok!(TimeKeeperModule::exit_account(Origin::signed(BOB), None));
+		assert_eq!(TimeKeeperModule::creditors(ORG, &BOB), Some(3 * RATE));
+	});
 }
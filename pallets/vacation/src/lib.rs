@@ -0,0 +1,306 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+use codec::{Encode, Decode};
+use frame_support::sp_std::convert::TryInto;
+use frame_support::sp_std::prelude::*;
+use frame_support::{decl_error, decl_event, decl_module, decl_storage, dispatch, ensure, traits::Get};
+use frame_system::ensure_signed;
+use sp_runtime::RuntimeDebug;
+use sp_runtime::traits::{Saturating, Zero};
+use pallet_timestamp as timestamp;
+use pallet_timekeeper as timekeeper;
+use timekeeper::OrgId;
+
+#[cfg(test)]
+mod mock;
+
+#[cfg(test)]
+mod tests;
+
+const MILLISECS_PER_HOUR: u32 = 60 * 60 * 1000;
+
+/// The vacation allowance of an account for a year
+#[derive(Encode, Decode, Clone, Default, PartialEq, Eq, RuntimeDebug)]
+pub struct Allowance {
+	/// The organization granting the allowance and paying for unused hours
+	pub org: OrgId,
+	/// Hours of vacation allocated each year
+	pub hours: u32,
+	/// Hours left to book this year
+	pub remaining: u32,
+	/// The year the remaining hours are for
+	pub year: u32,
+}
+
+/// Vacation booked by an account
+#[derive(Encode, Decode, Clone, Default, PartialEq, Eq, RuntimeDebug)]
+pub struct Booking<Moment> {
+	/// When the vacation starts
+	pub start: Moment,
+	/// Hours of vacation booked
+	pub hours: u32,
+	/// The year of the allowance the hours were taken from
+	pub year: u32,
+	/// Whether an administrator has approved the booking
+	pub approved: bool,
+}
+
+pub trait Trait: timekeeper::Trait {
+	type Event: From<Event<Self>> + Into<<Self as frame_system::Trait>::Event>;
+	/// Vacation is booked in multiples of this number of hours
+	type BookingUnit: Get<u32>;
+	/// The number of blocks in a vacation year, unused hours are credited at the end of the year
+	type VacationYear: Get<Self::BlockNumber>;
+}
+
+decl_storage! {
+	trait Store for Module<T: Trait> as Vacation {
+		/// Store the vacation allowance of an account
+		pub Allowances get(fn allowances): map hasher(blake2_128_concat) T::AccountId => Option<Allowance>;
+		/// Store the vacation booked by an account, by booking index
+		pub Bookings get(fn bookings):
+			double_map hasher(blake2_128_concat) T::AccountId, hasher(twox_64_concat) u32 => Option<Booking<T::Moment>>;
+		/// Number of bookings made by an account, which is also the index of the next booking
+		pub BookingCount get(fn booking_count): map hasher(blake2_128_concat) T::AccountId => u32;
+		/// Indexes of the bookings of an account waiting for approval
+		pub UnapprovedBookings get(fn unapproved_bookings): map hasher(blake2_128_concat) T::AccountId => Vec<u32>;
+	}
+}
+
+decl_event!(
+	pub enum Event<T>
+	where AccountId = <T as frame_system::Trait>::AccountId,
+	Balance = timekeeper::BalanceOf<T>,
+	Moment = <T as timestamp::Trait>::Moment {
+		/// The vacation allowance of an account has been set by an organization
+		/// [organization, account, hours]
+		AllowanceSet(OrgId, AccountId, u32),
+		/// Vacation has been booked
+		/// [account, booking index, start, hours]
+		VacationBooked(AccountId, u32, Moment, u32),
+		/// A booking has been approved by an administrator
+		/// [caller, account, booking index]
+		VacationApproved(AccountId, AccountId, u32),
+		/// A booking has been rejected by an administrator and the hours returned
+		/// [caller, account, booking index]
+		VacationRejected(AccountId, AccountId, u32),
+		/// A booking has been cancelled by the account and the hours returned
+		/// [account, booking index]
+		VacationCancelled(AccountId, u32),
+		/// The hours not taken in a year have been credited to the account
+		/// [account, hours, credit]
+		UnusedVacationCredited(AccountId, u32, Balance),
+		/// A booking wasn't approved before it started or before its year ended and the hours
+		/// have been returned
+		/// [account, booking index]
+		VacationExpired(AccountId, u32),
+	}
+);
+
+decl_error! {
+	pub enum Error for Module<T: Trait> {
+		ErrorAdminRoleRequired,
+		ErrorNoAllowance,
+		ErrorInsufficientAllowance,
+		ErrorInvalidBookingHours,
+		ErrorBookingInPast,
+		ErrorBookingNotFound,
+		ErrorBookingAlreadyApproved,
+		ErrorBookingStarted,
+	}
+}
+
+decl_module! {
+	pub struct Module<T: Trait> for enum Call where origin: T::Origin {
+		type Error = Error<T>;
+
+		const BookingUnit: u32 = T::BookingUnit::get();
+		const VacationYear: T::BlockNumber = T::VacationYear::get();
+
+		fn deposit_event() = default;
+
+		/// Set the hours of vacation an account is allocated each year by an organization it is
+		/// registered with, replacing an allowance granted by another organization
+		#[weight = 10_000 + T::DbWeight::get().writes(1)]
+		pub fn set_allowance(origin, org: OrgId, account: T::AccountId, hours: u32) -> dispatch::DispatchResult {
+			let who = ensure_signed(origin)?;
+			Self::ensure_admin(org, &who)?;
+			<timekeeper::Module<T>>::check_if_registered(org, &account)?;
+			Self::settle(&account);
+			let year = Self::current_year();
+			Allowances::<T>::mutate(&account, |allowance| {
+				let used = allowance.as_ref()
+					.filter(|a| a.org == org)
+					.map(|a| a.hours.saturating_sub(a.remaining))
+					.unwrap_or(0);
+				*allowance = Some(Allowance {
+					org,
+					hours,
+					remaining: hours.saturating_sub(used),
+					year,
+				});
+			});
+			Self::deposit_event(RawEvent::AllowanceSet(org, account, hours));
+			Ok(())
+		}
+
+		/// Book vacation from `start` for a number of hours, which has to be a multiple of the
+		/// booking unit
+		#[weight = 10_000 + T::DbWeight::get().writes(4)]
+		pub fn book_vacation(origin, start: T::Moment, hours: u32) -> dispatch::DispatchResult {
+			let who = ensure_signed(origin)?;
+			let unit = T::BookingUnit::get();
+			ensure!(hours > 0 && unit > 0 && hours % unit == 0, Error::<T>::ErrorInvalidBookingHours);
+			ensure!(start > <timestamp::Module<T>>::get(), Error::<T>::ErrorBookingInPast);
+			Self::settle(&who);
+			let mut allowance = Self::allowances(&who).ok_or(Error::<T>::ErrorNoAllowance)?;
+			<timekeeper::Module<T>>::check_if_registered(allowance.org, &who)?;
+			ensure!(allowance.remaining >= hours, Error::<T>::ErrorInsufficientAllowance);
+			allowance.remaining -= hours;
+			let index = Self::booking_count(&who);
+			Bookings::<T>::insert(&who, index, Booking {
+				start,
+				hours,
+				year: allowance.year,
+				approved: false,
+			});
+			BookingCount::<T>::insert(&who, index + 1);
+			UnapprovedBookings::<T>::append(&who, index);
+			Allowances::<T>::insert(&who, allowance);
+			Self::deposit_event(RawEvent::VacationBooked(who, index, start, hours));
+			Ok(())
+		}
+
+		/// Approve a booking which hasn't started yet
+		#[weight = 10_000 + T::DbWeight::get().writes(2)]
+		pub fn approve_vacation(origin, account: T::AccountId, index: u32) -> dispatch::DispatchResult {
+			let who = ensure_signed(origin)?;
+			Self::ensure_allowance_admin(&account, &who)?;
+			let mut booking = Self::bookings(&account, index).ok_or(Error::<T>::ErrorBookingNotFound)?;
+			ensure!(!booking.approved, Error::<T>::ErrorBookingAlreadyApproved);
+			ensure!(<timestamp::Module<T>>::get() < booking.start, Error::<T>::ErrorBookingStarted);
+			booking.approved = true;
+			Bookings::<T>::insert(&account, index, booking);
+			UnapprovedBookings::<T>::mutate(&account, |indexes| indexes.retain(|i| *i != index));
+			Self::deposit_event(RawEvent::VacationApproved(who, account, index));
+			Ok(())
+		}
+
+		#[weight = 10_000 + T::DbWeight::get().writes(2)]
+		pub fn reject_vacation(origin, account: T::AccountId, index: u32) -> dispatch::DispatchResult {
+			let who = ensure_signed(origin)?;
+			Self::ensure_allowance_admin(&account, &who)?;
+			let booking = Self::bookings(&account, index).ok_or(Error::<T>::ErrorBookingNotFound)?;
+			ensure!(!booking.approved, Error::<T>::ErrorBookingAlreadyApproved);
+			Self::return_booking(&account, index, booking);
+			Self::deposit_event(RawEvent::VacationRejected(who, account, index));
+			Ok(())
+		}
+
+		/// Cancel a booking which hasn't started yet, returning the hours to the allowance
+		#[weight = 10_000 + T::DbWeight::get().writes(2)]
+		pub fn cancel_vacation(origin, index: u32) -> dispatch::DispatchResult {
+			let who = ensure_signed(origin)?;
+			Self::settle(&who);
+			let booking = Self::bookings(&who, index).ok_or(Error::<T>::ErrorBookingNotFound)?;
+			ensure!(<timestamp::Module<T>>::get() < booking.start, Error::<T>::ErrorBookingStarted);
+			Self::return_booking(&who, index, booking);
+			Self::deposit_event(RawEvent::VacationCancelled(who, index));
+			Ok(())
+		}
+
+		/// Credit an account for the hours it didn't take in previous years, anyone can call this
+		/// once the year has ended
+		#[weight = 10_000 + T::DbWeight::get().writes(2)]
+		pub fn settle_year(origin, account: T::AccountId) -> dispatch::DispatchResult {
+			let _ = ensure_signed(origin)?;
+			Self::settle(&account);
+			Ok(())
+		}
+	}
+}
+
+impl<T: Trait> Module<T> {
+
+	/// The current vacation year, counted in blocks since genesis
+	pub fn current_year() -> u32 {
+		let year = T::VacationYear::get();
+		if year.is_zero() {
+			return 0;
+		}
+		TryInto::<u32>::try_into(<frame_system::Module<T>>::block_number() / year).unwrap_or(u32::max_value())
+	}
+
+	fn ensure_admin(org: OrgId, who: &T::AccountId) -> dispatch::DispatchResult {
+		ensure!(<timekeeper::Module<T>>::is_admin(org, who), Error::<T>::ErrorAdminRoleRequired);
+		Ok(())
+	}
+
+	/// Bookings are reviewed by the administrators of the organization granting the allowance
+	fn ensure_allowance_admin(account: &T::AccountId, who: &T::AccountId) -> dispatch::DispatchResult {
+		let allowance = Self::allowances(account).ok_or(Error::<T>::ErrorNoAllowance)?;
+		Self::ensure_admin(allowance.org, who)
+	}
+
+	/// Remove a booking, returning the hours if they were taken from the current allowance
+	fn return_booking(account: &T::AccountId, index: u32, booking: Booking<T::Moment>) {
+		Bookings::<T>::remove(account, index);
+		if !booking.approved {
+			UnapprovedBookings::<T>::mutate(account, |indexes| indexes.retain(|i| *i != index));
+		}
+		Allowances::<T>::mutate(account, |allowance| {
+			if let Some(allowance) = allowance {
+				if allowance.year == booking.year {
+					allowance.remaining = allowance.remaining.saturating_add(booking.hours).min(allowance.hours);
+				}
+			}
+		});
+	}
+
+	/// If the allowance of the account is for a previous year, credit the hours not taken at the
+	/// account's rate, including the full allowance of any year in between, and renew the
+	/// allowance for the current year
+	fn settle(account: &T::AccountId) {
+		Self::expire_bookings(account);
+		let year = Self::current_year();
+		let mut allowance = match Self::allowances(account) {
+			Some(allowance) if allowance.year < year => allowance,
+			_ => return,
+		};
+		let skipped = year - allowance.year - 1;
+		let unused = allowance.remaining.saturating_add(allowance.hours.saturating_mul(skipped));
+		if unused > 0 {
+			let credit = match <timekeeper::Module<T>>::rates(allowance.org, account) {
+				Some(rate) => {
+					let time = T::Moment::from(unused).saturating_mul(T::Moment::from(MILLISECS_PER_HOUR));
+					<timekeeper::Module<T>>::calculate_credit(time, rate)
+				},
+				None => Zero::zero(),
+			};
+			if !credit.is_zero() {
+				<timekeeper::Module<T>>::add_credit(allowance.org, account, credit);
+			}
+			Self::deposit_event(RawEvent::UnusedVacationCredited(account.clone(), unused, credit));
+		}
+		allowance.remaining = allowance.hours;
+		allowance.year = year;
+		Allowances::<T>::insert(account, allowance);
+	}
+
+	/// Return the hours of the bookings which weren't approved before they started or before
+	/// the end of the year of their allowance
+	fn expire_bookings(account: &T::AccountId) {
+		let now = <timestamp::Module<T>>::get();
+		let year = Self::current_year();
+		for index in Self::unapproved_bookings(account) {
+			match Self::bookings(account, index) {
+				Some(booking) if booking.start <= now || booking.year < year => {
+					Self::return_booking(account, index, booking);
+					Self::deposit_event(RawEvent::VacationExpired(account.clone(), index));
+				},
+				Some(_) => {},
+				None => UnapprovedBookings::<T>::mutate(account, |indexes| indexes.retain(|i| *i != index)),
+			}
+		}
+	}
+}
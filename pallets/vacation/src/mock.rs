@@ -0,0 +1,180 @@
+use crate::{Module, Trait};
+use sp_core::H256;
+use frame_support::{
+	assert_ok,
+	impl_outer_origin, 
+	impl_outer_event, 
+	parameter_types, 
+	weights::Weight,
+	traits::TestRandomness,
+};
+use sp_runtime::{
+	traits::{BlakeTwo256, IdentityLookup}, testing::{Header, TestSignature, UintAuthorityId}, Perbill,
+};
+
+impl_outer_origin! {
+	pub enum Origin for Test where system = frame_system {}
+}
+
+mod vacation {
+	pub use super::super::*;
+}
+
+impl_outer_event! {
+	pub enum Event for Test {
+		frame_system<T>,
+		vacation<T>,
+		pallet_timekeeper<T>,
+		pallet_access<T>,
+		pallet_balances<T>,
+	}
+}
+// Configure a mock runtime to test the pallet.
+
+#[derive(Clone, Eq, PartialEq)]
+pub struct Test;
+parameter_types! {
+	pub const BlockHashCount: u64 = 250;
+	pub const MaximumBlockWeight: Weight = 1024;
+	pub const MaximumBlockLength: u32 = 2 * 1024;
+	pub const AvailableBlockRatio: Perbill = Perbill::from_percent(75);
+}
+
+impl frame_system::Trait for Test {
+	type BaseCallFilter = ();
+	type Origin = Origin;
+	type Call = ();
+	type Index = u64;
+	type BlockNumber = u64;
+	type Hash = H256;
+	type Hashing = BlakeTwo256;
+	type AccountId = u64;
+	type Lookup = IdentityLookup<Self::AccountId>;
+	type Header = Header;
+	type Event = Event;
+	type BlockHashCount = BlockHashCount;
+	type MaximumBlockWeight = MaximumBlockWeight;
+	type DbWeight = ();
+	type BlockExecutionWeight = ();
+	type ExtrinsicBaseWeight = ();
+	type MaximumExtrinsicWeight = MaximumBlockWeight;
+	type MaximumBlockLength = MaximumBlockLength;
+	type AvailableBlockRatio = AvailableBlockRatio;
+	type Version = ();
+	type PalletInfo = ();
+	type AccountData = pallet_balances::AccountData<u64>;
+	type OnNewAccount = ();
+	type OnKilledAccount = ();
+	type SystemWeightInfo = ();
+}
+
+parameter_types! {
+	pub const ExistentialDeposit: u64 = 1;
+	pub const MinimumPeriod: u64 = 5;
+}
+impl pallet_balances::Trait for Test {
+	type MaxLocks = ();
+	type Balance = u64;
+	type Event = Event;
+	type DustRemoval = ();
+	type ExistentialDeposit = ExistentialDeposit;
+	type AccountStore = System;
+	type WeightInfo = ();
+}
+
+impl pallet_timestamp::Trait for Test {
+	type Moment = u64;
+	type OnTimestampSet = ();
+	type MinimumPeriod = MinimumPeriod;
+	type WeightInfo = ();
+}
+
+parameter_types! {
+	pub const WhitelistRole: H256 = H256::repeat_byte(0xff);
+	pub const MaxRoleDepth: u32 = 4;
+	pub const MaxChildRoles: u32 = 4;
+}
+
+impl pallet_access::Trait for Test {
+	type Event = Event;
+	type WhitelistRole = WhitelistRole;
+	type AlwaysAllowed = ();
+	type MaxRoleDepth = MaxRoleDepth;
+	type MaxChildRoles = MaxChildRoles;
+}
+
+parameter_types! {
+	pub const MaxShiftDuration: u64 = 8 * 60 * 60 * 1000;
+	pub const PayrollPeriod: u64 = 10;
+	pub const PayrollBatchSize: u32 = 2;
+	pub const AutoExitBatchSize: u32 = 2;
+	pub const RateProposalExpiry: u64 = 5;
+	pub const AutoBreakAfter: u64 = 0;
+	pub const AutoBreakDuration: u64 = 0;
+	pub const MaxChallengeAge: u64 = 0;
+	pub const MaxBackdating: u64 = 0;
+	pub const MaxOfflinePunches: u32 = 0;
+}
+
+impl pallet_timekeeper::Trait for Test {
+	type Event = Event;
+	type Currency = Balances;
+	type MaxShiftDuration = MaxShiftDuration;
+	type PayrollPeriod = PayrollPeriod;
+	type PayrollBatchSize = PayrollBatchSize;
+	type AutoExitBatchSize = AutoExitBatchSize;
+	type RateProposalExpiry = RateProposalExpiry;
+	type AutoBreakAfter = AutoBreakAfter;
+	type AutoBreakDuration = AutoBreakDuration;
+	type Public = UintAuthorityId;
+	type Signature = TestSignature;
+	type Randomness = TestRandomness;
+	type MaxChallengeAge = MaxChallengeAge;
+	type MaxBackdating = MaxBackdating;
+	type MaxOfflinePunches = MaxOfflinePunches;
+}
+
+parameter_types! {
+	pub const BookingUnit: u32 = 4;
+	pub const VacationYear: u64 = 100;
+}
+
+impl Trait for Test {
+	type Event = Event;
+	type BookingUnit = BookingUnit;
+	type VacationYear = VacationYear;
+}
+
+pub type VacationModule = Module<Test>;
+pub type TimeKeeperModule = pallet_timekeeper::Module<Test>;
+pub type AccessModule = pallet_access::Module<Test>;
+pub type System = frame_system::Module<Test>;
+pub type Balances = pallet_balances::Module<Test>;
+pub type Timestamp = pallet_timestamp::Module<Test>;
+
+// Build genesis storage according to the mock runtime.
+pub fn new_test_ext() -> sp_io::TestExternalities {
+	let mut t = frame_system::GenesisConfig::default().build_storage::<Test>().unwrap();
+
+	pallet_timekeeper::GenesisConfig::default().assimilate_storage::<Test>(&mut t).unwrap();
+
+	let mut t: sp_io::TestExternalities = t.into();
+	t.execute_with(|| System::set_block_number(1) );
+	t
+}
+
+/// The organization created by `setup_account`
+pub const ORG: u32 = 0;
+
+/// Make `admin` the administrator of a new organization and register `account` with it at `rate`
+pub fn setup_account(admin: u64, account: u64, rate: u64) {
+	assert_ok!(TimeKeeperModule::setup(Origin::root(), admin));
+	assert_ok!(TimeKeeperModule::create_organization(Origin::signed(admin), admin));
+	let registrar = TimeKeeperModule::organizations(ORG).unwrap().registrar_role;
+	assert_ok!(AccessModule::grant_role(Origin::signed(admin), registrar, admin, None));
+	assert_ok!(TimeKeeperModule::register_account(Origin::signed(admin), ORG, account, Some(pallet_timekeeper::RateSpec::Hourly(rate))));
+}
+
+pub fn last_event() -> Event {
+	System::events().last().unwrap().event.clone()
+}
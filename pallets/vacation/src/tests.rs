@@ -0,0 +1,129 @@
+use crate::{
+	RawEvent,
+	Error,
+	Allowance,
+	Booking,
+	mock::*
+};
+use frame_support::{assert_ok, assert_noop};
+
+const BOB : u64 = 101;
+const ALICE : u64 = 100;
+const RATE : u64 = 10;
+const HOUR : u64 = 60 * 60 * 1000;
+
+#[test]
+fn it_books_and_approves_vacation() {
+	new_test_ext().execute_with(|| {
+		setup_account(ALICE, BOB, RATE);
+		// BOB can't book without an allowance
+		assert_noop!(VacationModule::book_vacation(Origin::signed(BOB), HOUR, 8), Error::<Test>::ErrorNoAllowance);
+		// Only an administrator sets the allowance
+		assert_noop!(VacationModule::set_allowance(Origin::signed(BOB), ORG, BOB, 40), Error::<Test>::ErrorAdminRoleRequired);
+		assert_ok!(VacationModule::set_allowance(Origin::signed(ALICE), ORG, BOB, 40));
+		assert_eq!(VacationModule::allowances(&BOB), Some(Allowance { org: ORG, hours: 40, remaining: 40, year: 0 }));
+		// Bookings are made in multiples of the booking unit, in the future and within the allowance
+		assert_noop!(VacationModule::book_vacation(Origin::signed(BOB), HOUR, 6), Error::<Test>::ErrorInvalidBookingHours);
+		assert_noop!(VacationModule::book_vacation(Origin::signed(BOB), 0, 8), Error::<Test>::ErrorBookingInPast);
+		assert_noop!(VacationModule::book_vacation(Origin::signed(BOB), HOUR, 44), Error::<Test>::ErrorInsufficientAllowance);
+		assert_ok!(VacationModule::book_vacation(Origin::signed(BOB), HOUR, 8));
+		assert_eq!(last_event(), Event::vacation(RawEvent::VacationBooked(BOB, 0, HOUR, 8)));
+		assert_eq!(VacationModule::allowances(&BOB).unwrap().remaining, 32);
+		// ALICE approves the booking
+		assert_noop!(VacationModule::approve_vacation(Origin::signed(BOB), BOB, 0), Error::<Test>::ErrorAdminRoleRequired);
+		assert_ok!(VacationModule::approve_vacation(Origin::signed(ALICE), BOB, 0));
+		assert_eq!(VacationModule::bookings(&BOB, 0), Some(Booking { start: HOUR, hours: 8, year: 0, approved: true }));
+		assert_noop!(VacationModule::approve_vacation(Origin::signed(ALICE), BOB, 0), Error::<Test>::ErrorBookingAlreadyApproved);
+		// A rejected booking returns the hours
+		assert_ok!(VacationModule::book_vacation(Origin::signed(BOB), 2 * HOUR, 4));
+		assert_ok!(VacationModule::reject_vacation(Origin::signed(ALICE), BOB, 1));
+		assert_eq!(VacationModule::bookings(&BOB, 1), None);
+		assert_eq!(VacationModule::allowances(&BOB).unwrap().remaining, 32);
+	});
+}
+
+#[test]
+fn it_cancels_vacation_before_it_starts() {
+	new_test_ext().execute_with(|| {
+		setup_account(ALICE, BOB, RATE);
+		assert_ok!(VacationModule::set_allowance(Origin::signed(ALICE), ORG, BOB, 40));
+		assert_ok!(VacationModule::book_vacation(Origin::signed(BOB), HOUR, 8));
+		assert_ok!(VacationModule::book_vacation(Origin::signed(BOB), 2 * HOUR, 8));
+		assert_ok!(VacationModule::approve_vacation(Origin::signed(ALICE), BOB, 0));
+		assert_eq!(VacationModule::allowances(&BOB).unwrap().remaining, 24);
+		// BOB cancels the second booking and gets the hours back
+		assert_ok!(VacationModule::cancel_vacation(Origin::signed(BOB), 1));
+		assert_eq!(last_event(), Event::vacation(RawEvent::VacationCancelled(BOB, 1)));
+		assert_eq!(VacationModule::allowances(&BOB).unwrap().remaining, 32);
+		// The first booking has started and can't be cancelled
+		Timestamp::set_timestamp(HOUR);
+		assert_noop!(VacationModule::cancel_vacation(Origin::signed(BOB), 0), Error::<Test>::ErrorBookingStarted);
+		assert_noop!(VacationModule::cancel_vacation(Origin::signed(BOB), 1), Error::<Test>::ErrorBookingNotFound);
+	});
+}
+
+#[test]
+fn it_credits_unused_vacation_at_the_end_of_the_year() {
+	new_test_ext().execute_with(|| {
+		setup_account(ALICE, BOB, RATE);
+		assert_ok!(VacationModule::set_allowance(Origin::signed(ALICE), ORG, BOB, 40));
+		assert_ok!(VacationModule::book_vacation(Origin::signed(BOB), HOUR, 8));
+		assert_ok!(VacationModule::approve_vacation(Origin::signed(ALICE), BOB, 0));
+		// Nothing to settle during the year
+		assert_ok!(VacationModule::settle_year(Origin::signed(ALICE), BOB));
+		assert_eq!(TimeKeeperModule::creditors(ORG, &BOB), None);
+		// The 32 hours not taken are credited once the year has ended
+		System::set_block_number(VacationYear::get());
+		assert_ok!(VacationModule::settle_year(Origin::signed(ALICE), BOB));
+		assert_eq!(last_event(), Event::vacation(RawEvent::UnusedVacationCredited(BOB, 32, 32 * RATE)));
+		assert_eq!(TimeKeeperModule::creditors(ORG, &BOB), Some(32 * RATE));
+		assert_eq!(VacationModule::allowances(&BOB), Some(Allowance { org: ORG, hours: 40, remaining: 40, year: 1 }));
+		// Settling again doesn't credit twice
+		assert_ok!(VacationModule::settle_year(Origin::signed(ALICE), BOB));
+		assert_eq!(TimeKeeperModule::creditors(ORG, &BOB), Some(32 * RATE));
+	});
+}
+
+#[test]
+fn it_credits_every_year_not_settled() {
+	new_test_ext().execute_with(|| {
+		setup_account(ALICE, BOB, RATE);
+		assert_ok!(VacationModule::set_allowance(Origin::signed(ALICE), ORG, BOB, 40));
+		assert_ok!(VacationModule::book_vacation(Origin::signed(BOB), HOUR, 8));
+		assert_ok!(VacationModule::approve_vacation(Origin::signed(ALICE), BOB, 0));
+		// Nobody settles the first two years, the 32 hours left and the whole second year are credited
+		System::set_block_number(2 * VacationYear::get());
+		assert_ok!(VacationModule::settle_year(Origin::signed(ALICE), BOB));
+		assert_eq!(last_event(), Event::vacation(RawEvent::UnusedVacationCredited(BOB, 72, 72 * RATE)));
+		assert_eq!(TimeKeeperModule::creditors(ORG, &BOB), Some(72 * RATE));
+		assert_eq!(VacationModule::allowances(&BOB), Some(Allowance { org: ORG, hours: 40, remaining: 40, year: 2 }));
+	});
+}
+
+#[test]
+fn it_returns_bookings_not_approved_in_time() {
+	new_test_ext().execute_with(|| {
+		setup_account(ALICE, BOB, RATE);
+		assert_ok!(VacationModule::set_allowance(Origin::signed(ALICE), ORG, BOB, 40));
+		assert_ok!(VacationModule::book_vacation(Origin::signed(BOB), HOUR, 8));
+		assert_ok!(VacationModule::book_vacation(Origin::signed(BOB), 2 * HOUR, 8));
+		assert_eq!(VacationModule::unapproved_bookings(&BOB), vec![0, 1]);
+		// The first booking starts before it is approved and can no longer be approved
+		Timestamp::set_timestamp(HOUR);
+		assert_noop!(VacationModule::approve_vacation(Origin::signed(ALICE), BOB, 0), Error::<Test>::ErrorBookingStarted);
+		// Its hours are returned on the next booking
+		assert_ok!(VacationModule::book_vacation(Origin::signed(BOB), 3 * HOUR, 4));
+		assert_eq!(VacationModule::bookings(&BOB, 0), None);
+		assert_eq!(VacationModule::allowances(&BOB).unwrap().remaining, 28);
+		assert_eq!(VacationModule::unapproved_bookings(&BOB), vec![1, 2]);
+		assert_ok!(VacationModule::approve_vacation(Origin::signed(ALICE), BOB, 1));
+		assert_eq!(VacationModule::unapproved_bookings(&BOB), vec![2]);
+		// The year ends before the last booking is approved, its hours are credited as unused
+		System::set_block_number(VacationYear::get());
+		assert_ok!(VacationModule::settle_year(Origin::signed(ALICE), BOB));
+		assert_eq!(VacationModule::bookings(&BOB, 2), None);
+		assert_eq!(VacationModule::unapproved_bookings(&BOB), Vec::<u32>::new());
+		assert_eq!(TimeKeeperModule::creditors(ORG, &BOB), Some(32 * RATE));
+		assert_eq!(VacationModule::allowances(&BOB), Some(Allowance { org: ORG, hours: 40, remaining: 40, year: 1 }));
+	});
+}
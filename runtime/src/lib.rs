@@ -13,7 +13,7 @@ use sp_runtime::{
 	transaction_validity::{TransactionValidity, TransactionSource},
 };
 use sp_runtime::traits::{
-	BlakeTwo256, Block as BlockT, IdentityLookup, Verify, IdentifyAccount, NumberFor, Saturating,
+	BlakeTwo256, Block as BlockT, Hash as HashT, IdentityLookup, Verify, IdentifyAccount, NumberFor, Saturating,
 };
 use sp_api::impl_runtime_apis;
 use sp_consensus_aura::sr25519::AuthorityId as AuraId;
@@ -31,7 +31,7 @@ pub use pallet_balances::Call as BalancesCall;
 pub use sp_runtime::{Permill, Perbill};
 pub use frame_support::{
 	construct_runtime, parameter_types, StorageValue,
-	traits::{KeyOwnerProofSystem, Randomness},
+	traits::{Filter, KeyOwnerProofSystem, Randomness},
 	weights::{
 		Weight, IdentityFee,
 		constants::{BlockExecutionWeight, ExtrinsicBaseWeight, RocksDbWeight, WEIGHT_PER_SECOND},
@@ -41,6 +41,7 @@ pub use frame_support::{
 /// Import the timekeeper pallet.
 pub use pallet_timekeeper;
 pub use pallet_access;
+pub use pallet_vacation;
 
 /// An index to a block.
 pub type BlockNumber = u32;
@@ -96,10 +97,10 @@ pub const VERSION: RuntimeVersion = RuntimeVersion {
 	spec_name: create_runtime_str!("node-template"),
 	impl_name: create_runtime_str!("node-template"),
 	authoring_version: 1,
-	spec_version: 1,
+	spec_version: 2,
 	impl_version: 1,
 	apis: RUNTIME_API_VERSIONS,
-	transaction_version: 1,
+	transaction_version: 2,
 };
 
 pub const MILLISECS_PER_BLOCK: u64 = 6000;
@@ -262,14 +263,90 @@ impl pallet_sudo::Trait for Runtime {
 	type Call = Call;
 }
 
+parameter_types! {
+	/// Only accounts holding the whitelist role can submit transactions.
+	pub WhitelistRole: Hash = BlakeTwo256::hash(b"access-whitelist");
+	/// Role hierarchies are at most 8 roles deep.
+	pub const MaxRoleDepth: u32 = 8;
+	/// Roles have at most 16 child roles.
+	pub const MaxChildRoles: u32 = 16;
+}
+
+/// Calls accounts can submit without being whitelisted.
+pub struct AlwaysAllowed;
+impl Filter<Call> for AlwaysAllowed {
+	fn filter(call: &Call) -> bool {
+		// Sudo checks the key itself, so the whitelist can always be repaired
+		matches!(call, Call::Sudo(_))
+	}
+}
+
+/// Makes the sudo key a whitelist administrator of a chain upgraded from before the whitelist, so
+/// it can whitelist the other accounts.
+pub struct SeedWhitelist;
+impl frame_support::traits::OnRuntimeUpgrade for SeedWhitelist {
+	fn on_runtime_upgrade() -> Weight {
+		Access::seed_whitelist(Sudo::key())
+	}
+}
+
 impl pallet_access::Trait for Runtime {
 	type Event = Event;
+	type WhitelistRole = WhitelistRole;
+	type AlwaysAllowed = AlwaysAllowed;
+	type MaxRoleDepth = MaxRoleDepth;
+	type MaxChildRoles = MaxChildRoles;
+}
+
+parameter_types! {
+	/// Accounts are exited automatically after 8 hours.
+	pub const MaxShiftDuration: u64 = 8 * 60 * 60 * 1000;
+	/// Creditors are paid once a day, 50 accounts per block.
+	pub const PayrollPeriod: BlockNumber = DAYS;
+	pub const PayrollBatchSize: u32 = 50;
+	/// Entered accounts are checked for their maximum shift duration 100 per block.
+	pub const AutoExitBatchSize: u32 = 100;
+	/// Rate proposals have to be accepted within a week.
+	pub const RateProposalExpiry: BlockNumber = 7 * DAYS;
+	/// Shifts over 6 hours without a break have a 30 minute unpaid break deducted.
+	pub const AutoBreakAfter: u64 = 6 * 60 * 60 * 1000;
+	pub const AutoBreakDuration: u64 = 30 * 60 * 1000;
+	/// Kiosk challenge codes are accepted for a minute after they are shown.
+	pub const MaxChallengeAge: BlockNumber = MINUTES;
+	/// Devices can record punches offline for up to three days, submitted in batches of 50.
+	pub const MaxBackdating: u64 = 3 * 24 * 60 * 60 * 1000;
+	pub const MaxOfflinePunches: u32 = 50;
 }
 
 // /// Configure the timekeeper pallet in pallets/timekeeper.
 impl pallet_timekeeper::Trait for Runtime {
 	type Event = Event;
 	type Currency = Balances;	
+	type MaxShiftDuration = MaxShiftDuration;
+	type PayrollPeriod = PayrollPeriod;
+	type PayrollBatchSize = PayrollBatchSize;
+	type AutoExitBatchSize = AutoExitBatchSize;
+	type RateProposalExpiry = RateProposalExpiry;
+	type AutoBreakAfter = AutoBreakAfter;
+	type AutoBreakDuration = AutoBreakDuration;
+	type Public = <Signature as Verify>::Signer;
+	type Signature = Signature;
+	type Randomness = RandomnessCollectiveFlip;
+	type MaxChallengeAge = MaxChallengeAge;
+	type MaxBackdating = MaxBackdating;
+	type MaxOfflinePunches = MaxOfflinePunches;
+}
+
+parameter_types! {
+	/// Vacation is booked in half days and unused hours are credited once a year.
+	pub const BookingUnit: u32 = 4;
+	pub const VacationYear: BlockNumber = 365 * DAYS;
+}
+
+impl pallet_vacation::Trait for Runtime {
+	type Event = Event;
+	type BookingUnit = BookingUnit;
+	type VacationYear = VacationYear;
 }
 
 // Create the runtime by composing the FRAME pallets that were previously configured.
@@ -288,8 +365,9 @@ construct_runtime!(
 		TransactionPayment: pallet_transaction_payment::{Module, Storage},
 		Sudo: pallet_sudo::{Module, Call, Config<T>, Storage, Event<T>},
 		// Include the custom logic from the time keeper pallet in the runtime.
-		TimeKeeper: pallet_timekeeper::{Module, Call, Storage, Event<T>, Config},
-		Access: pallet_access::{Module, Call, Storage, Event<T>},
+		TimeKeeper: pallet_timekeeper::{Module, Call, Storage, Event<T>, Config, ValidateUnsigned},
+		Access: pallet_access::{Module, Call, Storage, Event<T>, Config<T>},
+		Vacation: pallet_vacation::{Module, Call, Storage, Event<T>},
 	}
 );
 
@@ -311,6 +389,7 @@ pub type SignedExtra = (
 	frame_system::CheckEra<Runtime>,
 	frame_system::CheckNonce<Runtime>,
 	frame_system::CheckWeight<Runtime>,
+	pallet_access::CheckRole<Runtime>,
 	pallet_transaction_payment::ChargeTransactionPayment<Runtime>
 );
 /// Unchecked extrinsic type as expected by this runtime.
@@ -324,6 +403,7 @@ pub type Executive = frame_executive::Executive<
 	frame_system::ChainContext<Runtime>,
 	Runtime,
 	AllModules,
+	SeedWhitelist,
 >;
 
 impl_runtime_apis! {
@@ -483,6 +563,7 @@ impl_runtime_apis! {
 			let mut batches = Vec::<BenchmarkBatch>::new();
 			let params = (&config, &whitelist);
 
+			add_benchmark!(params, batches, pallet_access, Access);
 			add_benchmark!(params, batches, frame_system, SystemBench::<Runtime>);
 			add_benchmark!(params, batches, pallet_balances, Balances);
 			add_benchmark!(params, batches, pallet_timestamp, Timestamp);